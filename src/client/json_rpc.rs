@@ -1,123 +1,504 @@
-use crate::utils::timeout::enforce_timeout;
+use crate::utils::timeout::{enforce_timeout, timeout};
 
-use super::transport::Transport;
+use super::transport::{Transport, TransportError};
 use anyhow::Result;
 use futures::{
     channel::{mpsc, oneshot},
-    future, Sink, SinkExt, Stream, StreamExt,
+    future::{self, LocalBoxFuture},
+    Sink, SinkExt, Stream, StreamExt,
 };
-use js_sys::JsString;
-use json_rpc_types::{str_buf::StrBuf, Id, Request, Response, Version};
+use json_rpc_types::{str_buf::StrBuf, ErrorCode, Id, Request, Response, Version};
 use serde::{de::DeserializeOwned, Serialize};
-use serde_json::Value;
+use serde_json::{value::RawValue, Value};
 use std::{
+    cell::{Cell, RefCell},
     collections::HashMap,
+    rc::{Rc, Weak},
     sync::{
         atomic::{AtomicU64, Ordering},
-        Arc, Mutex,
+        Arc, Mutex, PoisonError,
     },
     time::Duration,
 };
 use thiserror::Error;
 use tokio::sync::broadcast;
-use wasm_bindgen::{prelude::Closure, JsCast};
-use web_sys::{Event, MessageEvent};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use web_sys::WebSocket;
 
-type PendingMessagesStore = Arc<Mutex<HashMap<u64, oneshot::Sender<Response<Value, Value>>>>>;
+/// Default capacity of the notification channels.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 32;
+
+/// Default maximum size of a received message, in bytes.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Policy used to resend requests that failed because of the connection.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Maximum number of times a request is resent, disabled if zero.
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub base_delay: Duration,
+    /// Upper bound of the delay between retries.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay to wait before the given retry (starting at 0),
+    /// doubling it on every retry up to `max_delay`.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Returns whether a request failing with the given error can be resent.
+    ///
+    /// Only connection failures are retried. Error responses are final and a
+    /// timed out request may still be processed, so resending it could
+    /// duplicate it.
+    fn is_retriable(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<JsonRpcError>(),
+            Some(JsonRpcError::ConnectionClosed)
+        ) || matches!(
+            error.downcast_ref::<TransportError>(),
+            Some(TransportError::SendingMessage(_))
+        )
+    }
+}
+
+/// Maximum number of notifications sent per interval, to pace bursts like a
+/// broadcast round to many parties.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub max_messages: u32,
+    pub interval: Duration,
+}
+
+/// Paces the notifications of every sender according to a `RateLimit`,
+/// counting the ones sent in the current interval.
+struct RateLimiter {
+    limit: RateLimit,
+    window_start: Cell<f64>,
+    sent: Cell<u32>,
+}
+
+impl RateLimiter {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            window_start: Cell::new(0.0),
+            sent: Cell::new(0),
+        }
+    }
+
+    /// Waits until a notification can be sent, counting it.
+    async fn acquire(&self) {
+        let interval_ms = self.limit.interval.as_secs_f64() * 1000.0;
+        loop {
+            let now = js_sys::Date::now();
+            if now - self.window_start.get() >= interval_ms {
+                self.window_start.set(now);
+                self.sent.set(0);
+            }
+            if self.sent.get() < self.limit.max_messages {
+                self.sent.set(self.sent.get() + 1);
+                return;
+            }
+            let wait_ms = self.window_start.get() + interval_ms - now;
+            timeout(Duration::from_secs_f64(wait_ms.max(0.0) / 1000.0)).await;
+        }
+    }
+}
+
+/// Notification whose params are kept as raw JSON until a receiver
+/// deserializes them into their final type.
+type Notification = Request<Box<RawValue>>;
+
+/// Senders waiting for the response of each request. It's only locked for
+/// synchronous updates, never across await points.
+type PendingMessagesStore = Arc<Mutex<HashMap<u64, oneshot::Sender<PendingResponse>>>>;
+
+/// Outcome of a pending request, failed if it's cancelled.
+type PendingResponse = std::result::Result<Response<Value, Value>, JsonRpcError>;
+
+/// Handler of a request sent by the host, producing the result (or error)
+/// of the response sent back.
+pub type RequestHandler = Box<
+    dyn Fn(Option<Value>) -> LocalBoxFuture<'static, Result<Value, json_rpc_types::Error<Value>>>,
+>;
+
+type RequestHandlers = Rc<RefCell<HashMap<String, RequestHandler>>>;
 
 #[derive(Debug, Error)]
 pub enum JsonRpcError {
     #[error("notification `${0}` was received without params")]
     NotificationWithoutParams(String),
+    #[error("connection closed before receiving a response")]
+    ConnectionClosed,
+    #[error("request was cancelled")]
+    Cancelled,
+    #[error("instance closed")]
+    Closed,
+    #[error("receiver lagged behind, `{0}` messages were skipped")]
+    LaggedReceiver(u64),
+}
+
+/// Connection events emitted by the underlying transport.
+///
+/// They serialize to the reason object passed to the JS callbacks.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum ConnectionEvent {
+    /// The connection was (re)opened.
+    Opened,
+    /// The connection was closed and all pending requests were failed.
+    Closed { code: u16, reason: String },
+    /// The connection errored out or a message couldn't be sent.
+    Error { message: String },
+}
+
+/// Removes the given requests from the pending messages store when dropped,
+/// so requests that time out or get cancelled don't leave their senders behind.
+struct PendingGuard<'a> {
+    pending_messages: &'a PendingMessagesStore,
+    req_ids: Vec<u64>,
+}
+
+impl<'a> PendingGuard<'a> {
+    fn new(pending_messages: &'a PendingMessagesStore, req_ids: Vec<u64>) -> Self {
+        Self {
+            pending_messages,
+            req_ids,
+        }
+    }
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        let mut pending_messages = self
+            .pending_messages
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for req_id in self.req_ids.iter() {
+            pending_messages.remove(req_id);
+        }
+    }
 }
 
 pub struct JsonRpc {
-    transport: Transport,
-    message_id: AtomicU64,
+    transport: Rc<dyn Transport>,
+    message_id: Arc<AtomicU64>,
     pending_messages: PendingMessagesStore,
-    notification_tx: broadcast::Sender<Request<Value>>,
+    notification_tx: broadcast::Sender<Arc<Notification>>,
+    connection_event_tx: broadcast::Sender<ConnectionEvent>,
+    request_handlers: RequestHandlers,
     timeout: Duration,
+    retry_policy: RetryPolicy,
+    rate_limiter: Option<Rc<RateLimiter>>,
+    closed: Cell<bool>,
 }
 
 impl JsonRpc {
     /// Creates a new `JsonRpc`.
-    pub fn new(url: String, timeout: Option<Duration>) -> Result<Self> {
-        let transport = Transport::new(url)?;
+    ///
+    /// Received messages larger than `max_message_size` bytes are dropped
+    /// without being parsed.
+    ///
+    /// Its callbacks are owned by the transport, so they are released (and
+    /// the connection closed) once the `JsonRpc` is dropped.
+    pub fn new(
+        transport: impl Transport + 'static,
+        timeout: Option<Duration>,
+        channel_capacity: usize,
+        max_message_size: usize,
+    ) -> Self {
+        let transport: Rc<dyn Transport> = Rc::new(transport);
         let pending_messages: PendingMessagesStore = Arc::new(Mutex::new(HashMap::new()));
         let timeout = timeout.unwrap_or(Duration::from_secs(30));
 
         // Register channel to receive notifications
-        let (notification_tx, _) = broadcast::channel::<Request<Value>>(32);
+        let (notification_tx, _) = broadcast::channel::<Arc<Notification>>(channel_capacity);
 
         // Set onmessage callback to handle all received messages
+        let request_handlers: RequestHandlers = Rc::new(RefCell::new(HashMap::new()));
         let pending_messages_c = pending_messages.clone();
         let notification_tx_c = notification_tx.clone();
-        let onmessage_callback = Closure::<dyn FnMut(_)>::new(move |message: MessageEvent| {
-            // Check response is string otherwise return
-            let Ok(message) = message.data().dyn_into::<JsString>() else { return };
-            let message = String::from(message);
+        let request_handlers_c = request_handlers.clone();
+        let transport_c = Rc::downgrade(&transport);
+        transport.set_onmessage(Box::new(move |message: String| {
+            // Drop oversized messages before parsing them, whatever they are
+            if message.len() > max_message_size {
+                log::warn!(
+                    "Dropping message of {} bytes, larger than the maximum of {}",
+                    message.len(),
+                    max_message_size
+                );
+                return;
+            }
+
+            // Handle notification message. Notifications carry the protocol
+            // messages, so they are checked first and their params are kept raw
+            // to be deserialized only once by their receivers
+            if let Ok(message) = serde_json::from_str::<Notification>(&message) {
+                if !message.is_notification() {
+                    JsonRpc::handle_request(&request_handlers_c, &transport_c, message);
+                    return;
+                }
+                let _ = notification_tx_c.send(Arc::new(message)); // Ignores no receiver error
+                return;
+            }
 
             // Handle response message
             if let Ok(message) = serde_json::from_str::<Response<Value, Value>>(&message) {
-                // Validate message
-                let Some(Id::Num(res_id)) = message.id else { return };
-                log::debug!("Response received: {:?}", message);
-
-                // Return response to client, if any
-                let Some(tx) = pending_messages_c.lock().unwrap().remove(&res_id) else { return };
-                tx.send(message).unwrap(); //FIXME
+                JsonRpc::dispatch_response(&pending_messages_c, message);
                 return;
             }
 
-            // Handle notification message
-            if let Ok(message) = serde_json::from_str::<Request<Value>>(&message) {
-                if !message.is_notification() {
-                    return;
+            // Handle batch response message
+            if let Ok(messages) = serde_json::from_str::<Vec<Response<Value, Value>>>(&message) {
+                for message in messages {
+                    JsonRpc::dispatch_response(&pending_messages_c, message);
                 }
-                let _ = notification_tx_c.send(message); // Ignores no receiver error
             }
-        });
-        transport.set_onmessage(onmessage_callback.as_ref().unchecked_ref());
-        onmessage_callback.forget();
+        }));
 
-        let onopen_callback = Closure::<dyn FnMut(_)>::new(move |_: Event| {
+        let (connection_event_tx, _) = broadcast::channel::<ConnectionEvent>(32);
+        let connection_event_tx_c = connection_event_tx.clone();
+        transport.set_onopen(Box::new(move || {
             log::info!("Connected to host");
-        });
-        transport.set_onopen(onopen_callback.as_ref().unchecked_ref());
-        onopen_callback.forget();
+            let _ = connection_event_tx_c.send(ConnectionEvent::Opened); // Ignores no receiver error
+        }));
 
-        Ok(Self {
+        // Fail all pending requests as soon as the connection is closed,
+        // their responses will never arrive
+        let pending_messages_c = pending_messages.clone();
+        let connection_event_tx_c = connection_event_tx.clone();
+        transport.set_onclose(Box::new(move |code: u16, reason: String| {
+            log::warn!("Disconnected from host: {} {}", code, reason);
+            // Dropping the senders makes every waiting receiver error out
+            pending_messages_c
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clear();
+            let _ = connection_event_tx_c.send(ConnectionEvent::Closed { code, reason });
+            // Ignores no receiver error
+        }));
+
+        let connection_event_tx_c = connection_event_tx.clone();
+        transport.set_onerror(Box::new(move || {
+            log::error!("Connection error");
+            let _ = connection_event_tx_c.send(ConnectionEvent::Error {
+                message: "connection error".into(),
+            }); // Ignores no receiver error
+        }));
+
+        Self {
             transport,
-            message_id: AtomicU64::new(0),
+            message_id: Arc::new(AtomicU64::new(0)),
             pending_messages,
             notification_tx,
+            connection_event_tx,
+            request_handlers,
             timeout,
-        })
+            retry_policy: RetryPolicy::default(),
+            rate_limiter: None,
+            closed: Cell::new(false),
+        }
     }
 
-    /// Sends a new request.
+    /// Sets the policy used to resend requests that failed because of the
+    /// connection. Requests are not resent by default.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Limits the rate of the notifications sent by every notification
+    /// sender. They are sent as soon as possible by default.
+    pub fn set_rate_limit(&mut self, rate_limit: RateLimit) {
+        self.rate_limiter = Some(Rc::new(RateLimiter::new(rate_limit)));
+    }
+
+    /// Sets the handler of the requests of a given method sent by the host,
+    /// replacing the previous one. Requests without a handler are answered
+    /// with a method not found error.
+    pub fn set_request_handler(&self, method: String, handler: RequestHandler) {
+        self.request_handlers.borrow_mut().insert(method, handler);
+    }
+
+    /// Sends a new request, waiting for its response up to `timeout`.
     ///
-    /// Returns a oneshot channel to wait for the response.
-    pub async fn send_message<P: Serialize>(
+    /// Requests failing because of the connection are resent according to
+    /// the retry policy, with a new id so late responses are ignored. While
+    /// reconnecting, the transport buffers them until the connection opens.
+    pub async fn send_message_with_timeout<P: Serialize>(
         &self,
         method: String,
         params: Option<P>,
+        timeout: Duration,
+    ) -> Result<Response<Value, Value>> {
+        self.ensure_open()?;
+        let params = params.map(serde_json::to_value).transpose()?;
+        let mut retry = 0;
+        loop {
+            let req_id = self.next_message_id();
+            let req = JsonRpc::new_request(Some(req_id), method.clone(), params.clone());
+            let req = serde_json::to_string(&req)?;
+            let res = JsonRpc::request(
+                self.transport.as_ref(),
+                &self.pending_messages,
+                req_id,
+                &req,
+                timeout,
+            )
+            .await;
+
+            match res {
+                Err(e)
+                    if retry < self.retry_policy.max_retries && RetryPolicy::is_retriable(&e) =>
+                {
+                    let delay = self.retry_policy.delay(retry);
+                    retry += 1;
+                    log::warn!(
+                        "Request `{}` failed due to `{}`, retrying in {}ms (attempt {})",
+                        method,
+                        e,
+                        delay.as_millis(),
+                        retry
+                    );
+                    crate::utils::timeout::timeout(delay).await;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Sends a request and waits for its response.
+    async fn request(
+        transport: &dyn Transport,
+        pending_messages: &PendingMessagesStore,
+        req_id: u64,
+        req: &str,
+        timeout: Duration,
     ) -> Result<Response<Value, Value>> {
-        let req_id = self.next_message_id();
-        let req = JsonRpc::new_request(Some(req_id), method, params);
-        let req = serde_json::to_string(&req)?;
-        self.transport.send(&req)?;
+        transport.send(req)?;
 
         // Create oneshot channel to wait for response
-        let (tx, rx) = oneshot::channel::<Response<Value, Value>>();
+        let (tx, rx) = oneshot::channel::<PendingResponse>();
 
-        // Add to pending messages
-        self.pending_messages
+        // Add to pending messages, removing it again if the request times out
+        // or this future is dropped before receiving a response
+        pending_messages
             .lock()
-            .unwrap() //FIXME
+            .unwrap_or_else(PoisonError::into_inner)
             .insert(req_id, tx);
+        let _guard = PendingGuard::new(pending_messages, vec![req_id]);
+
+        let res = enforce_timeout(timeout, rx)
+            .await?
+            .map_err(|_| JsonRpcError::ConnectionClosed)??;
+        Ok(res)
+    }
+
+    /// Sends a `ping` request every `interval` while the connection is open,
+    /// reconnecting if no response arrives within the interval. Any response
+    /// proves the connection is alive, even an error one.
+    ///
+    /// Stops once the `JsonRpc` is dropped.
+    pub fn start_keepalive(&self, interval: Duration) {
+        let transport = Rc::downgrade(&self.transport);
+        let pending_messages = self.pending_messages.clone();
+        let message_id = self.message_id.clone();
+        let connection_event_tx = self.connection_event_tx.clone();
+
+        wasm_bindgen_futures::spawn_local(async move {
+            loop {
+                timeout(interval).await;
+                let Some(transport) = transport.upgrade() else { break };
+                if transport.ready_state() != WebSocket::OPEN {
+                    continue;
+                }
 
-        let res = enforce_timeout(self.timeout, rx).await??;
+                let req_id = message_id.fetch_add(1, Ordering::Relaxed);
+                let req = JsonRpc::new_request::<Value>(Some(req_id), "ping".into(), None);
+                let Ok(req) = serde_json::to_string(&req) else { continue };
+                let res = JsonRpc::request(
+                    transport.as_ref(),
+                    &pending_messages,
+                    req_id,
+                    &req,
+                    interval,
+                )
+                .await;
+                if res.is_ok() {
+                    continue;
+                }
+                log::warn!("Keepalive ping was not answered, reconnecting");
+                let _ = connection_event_tx.send(ConnectionEvent::Error {
+                    message: "keepalive ping was not answered".into(),
+                }); // Ignores no receiver error
+                if let Err(e) = transport.reconnect() {
+                    log::error!("Failed to reconnect: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Sends a batch of requests in a single message, waiting for their
+    /// responses up to `timeout`.
+    ///
+    /// Returns the responses in the same order as the requests.
+    pub async fn send_batch(
+        &self,
+        requests: Vec<(String, Option<Value>)>,
+        timeout: Duration,
+    ) -> Result<Vec<Response<Value, Value>>> {
+        self.ensure_open()?;
+        if requests.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let requests: Vec<_> = requests
+            .into_iter()
+            .map(|(method, params)| {
+                JsonRpc::new_request(Some(self.next_message_id()), method, params)
+            })
+            .collect();
+        let batch = serde_json::to_string(&requests)?;
+
+        // Create one oneshot channel per request to wait for its response
+        let mut receivers = Vec::with_capacity(requests.len());
+        let mut req_ids = Vec::with_capacity(requests.len());
+        {
+            let mut pending_messages = self
+                .pending_messages
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            for req in requests.iter() {
+                let Some(Id::Num(req_id)) = req.id else { continue };
+                let (tx, rx) = oneshot::channel::<PendingResponse>();
+                pending_messages.insert(req_id, tx);
+                receivers.push(rx);
+                req_ids.push(req_id);
+            }
+        }
+        let _guard = PendingGuard::new(&self.pending_messages, req_ids);
+        self.transport.send(&batch)?;
+
+        let res = enforce_timeout(timeout, future::try_join_all(receivers))
+            .await?
+            .map_err(|_| JsonRpcError::ConnectionClosed)?
+            .into_iter()
+            .collect::<std::result::Result<_, _>>()?;
         Ok(res)
     }
 
@@ -130,45 +511,206 @@ impl JsonRpc {
     {
         // Create stream to handle notifications
         self.get_notification_rx()
-            .filter_map(|message| {
+            .filter_map(move |message| {
                 future::ready(match message {
-                    Ok(message) => Some(message),
-                    Err(_) => None, //TODO: handle lagged receiver?
+                    Ok(message) if message.method.as_str() == method => Some(Ok(message)),
+                    Ok(_) => None,
+                    Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                        log::warn!("Notification receiver lagged, {} messages skipped", skipped);
+                        Some(Err(JsonRpcError::LaggedReceiver(skipped)))
+                    }
                 })
             })
-            .filter(move |message| future::ready(message.method.as_str() == method))
             .map(|message| {
-                let data = message
-                    .params
-                    .ok_or(JsonRpcError::NotificationWithoutParams(
-                        message.method.as_str().into(),
-                    ))?;
-                let actual_message: T = serde_json::from_value(data)?;
+                let message = message?;
+                let data = message.params.as_ref().ok_or_else(|| {
+                    JsonRpcError::NotificationWithoutParams(message.method.as_str().into())
+                })?;
+                let actual_message: T = serde_json::from_str(data.get())?;
                 Ok(actual_message)
             })
     }
 
     /// Creates a notification sender.
     ///
-    /// Returns a sink that consumes `Request` messages and sends them to the host.
+    /// Returns a sink that consumes `Request` messages and sends them to the host,
+    /// paced by the rate limit if any.
     pub fn get_notification_sender<T>(&self) -> impl Sink<Request<T>, Error = anyhow::Error>
     where
         T: Serialize + 'static,
     {
         let (tx, mut rx) = mpsc::unbounded::<Request<T>>();
-        let raw_transport = self.transport.get_raw();
+        let transport = self.transport.clone();
+        let connection_event_tx = self.connection_event_tx.clone();
+        let rate_limiter = self.rate_limiter.clone();
 
         wasm_bindgen_futures::spawn_local(async move {
             while let Some(req) = rx.next().await {
+                if let Some(rate_limiter) = &rate_limiter {
+                    rate_limiter.acquire().await;
+                }
                 let req = JsonRpc::new_request(None, req.method.as_str().into(), req.params);
                 let Ok(req) = serde_json::to_string(&req) else { continue };
-                raw_transport.send_with_str(&req).unwrap(); //FIXME
+                if let Err(e) = transport.send(&req) {
+                    log::error!("Failed to send notification: {}", e);
+                    let _ = connection_event_tx.send(ConnectionEvent::Error {
+                        message: format!("failed to send notification: {}", e),
+                    }); // Ignores no receiver error
+                }
             }
         });
 
         tx.sink_err_into()
     }
 
+    /// Returns the default timeout of requests.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Returns the ready state of the underlying websocket.
+    pub fn ready_state(&self) -> u16 {
+        self.transport.ready_state()
+    }
+
+    /// Closes the connection and opens a new one, waiting until it's open.
+    ///
+    /// Requests waiting for a response through the previous connection fail,
+    /// while the notification receivers are kept.
+    pub async fn reconnect(&self) -> Result<()> {
+        self.ensure_open()?;
+        let mut connection_events = self.connection_event_tx.subscribe();
+        self.transport.reconnect()?;
+        enforce_timeout(self.timeout, async move {
+            loop {
+                match connection_events.recv().await {
+                    Ok(ConnectionEvent::Opened) => return Ok(()),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err(JsonRpcError::ConnectionClosed.into())
+                    }
+                }
+            }
+        })
+        .await?
+    }
+
+    /// Reconnects to another url, as `reconnect` does. Following reconnections
+    /// use the new url too.
+    pub async fn set_url(&self, url: String) -> Result<()> {
+        self.ensure_open()?;
+        self.transport.set_url(url)?;
+        self.reconnect().await
+    }
+
+    /// Creates a receiver of connection events (open, close and error).
+    pub fn get_connection_event_receiver(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.connection_event_tx.subscribe()
+    }
+
+    /// Answers a request sent by the host with the result of its handler.
+    fn handle_request(
+        request_handlers: &RequestHandlers,
+        transport: &Weak<dyn Transport>,
+        request: Notification,
+    ) {
+        log::debug!("Request received: {:?}", request);
+        let params = request
+            .params
+            .and_then(|params| serde_json::from_str::<Value>(params.get()).ok());
+        let result = match request_handlers.borrow().get(request.method.as_str()) {
+            Some(handler) => handler(params),
+            None => {
+                log::warn!("No handler for request method {}", request.method.as_str());
+                Box::pin(future::ready(Err(json_rpc_types::Error::from_code(
+                    ErrorCode::MethodNotFound,
+                ))))
+            }
+        };
+
+        let id = request.id;
+        let transport = transport.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let response: Response<Value, Value> = match result.await {
+                Ok(result) => Response::result(Version::V2, result, id),
+                Err(error) => Response::error(Version::V2, error, id),
+            };
+            let Some(transport) = transport.upgrade() else { return };
+            let Ok(response) = serde_json::to_string(&response) else { return };
+            if let Err(e) = transport.send(&response) {
+                log::error!("Failed to send response: {}", e);
+            }
+        });
+    }
+
+    /// Returns a response to the client waiting for it, if any.
+    fn dispatch_response(pending_messages: &PendingMessagesStore, message: Response<Value, Value>) {
+        // Validate message
+        let Some(Id::Num(res_id)) = message.id else { return };
+        log::debug!("Response received: {:?}", message);
+
+        // Return response to client, if any
+        let Some(tx) = pending_messages
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&res_id)
+        else {
+            return;
+        };
+        if tx.send(Ok(message)).is_err() {
+            log::warn!("Response {} received after its request was dropped", res_id);
+        }
+    }
+
+    /// Returns the number of requests waiting for a response.
+    pub fn pending_requests(&self) -> usize {
+        self.pending_messages
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Fails every request waiting for a response with a cancelled error.
+    pub fn cancel_all(&self) {
+        log::info!("Cancelling pending requests");
+        self.fail_pending(|| JsonRpcError::Cancelled);
+    }
+
+    /// Closes the connection for good, failing the requests waiting for a
+    /// response and every request made from now on.
+    pub fn close(&self) {
+        if self.closed.replace(true) {
+            return;
+        }
+        log::info!("Closing connection to host");
+        self.transport.close();
+        self.fail_pending(|| JsonRpcError::Closed);
+    }
+
+    /// Returns whether the connection was closed with `close`.
+    pub fn is_closed(&self) -> bool {
+        self.closed.get()
+    }
+
+    fn ensure_open(&self) -> Result<(), JsonRpcError> {
+        if self.closed.get() {
+            return Err(JsonRpcError::Closed);
+        }
+        Ok(())
+    }
+
+    fn fail_pending(&self, error: impl Fn() -> JsonRpcError) {
+        let pending_messages = std::mem::take(
+            &mut *self
+                .pending_messages
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        for tx in pending_messages.into_values() {
+            let _ = tx.send(Err(error())); // Ignores dropped requests
+        }
+    }
+
     /// Returns message id to create a request and increases
     /// internal counter by 1.
     fn next_message_id(&self) -> u64 {
@@ -176,7 +718,7 @@ impl JsonRpc {
     }
 
     /// Returns a new notification rx channel
-    fn get_notification_rx(&self) -> tokio_stream::wrappers::BroadcastStream<Request<Value>> {
+    fn get_notification_rx(&self) -> tokio_stream::wrappers::BroadcastStream<Arc<Notification>> {
         tokio_stream::wrappers::BroadcastStream::new(self.notification_tx.subscribe())
     }
 
@@ -195,3 +737,94 @@ impl JsonRpc {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::timeout::EnforceTimeoutError;
+    use futures::FutureExt;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    /// Transport of a host that never answers.
+    struct SilentTransport;
+
+    impl Transport for SilentTransport {
+        fn send(&self, _message: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_onmessage(&self, _callback: Box<dyn FnMut(String)>) {}
+
+        fn set_onopen(&self, _callback: Box<dyn FnMut()>) {}
+
+        fn set_onerror(&self, _callback: Box<dyn FnMut()>) {}
+
+        fn set_onclose(&self, _callback: Box<dyn FnMut(u16, String)>) {}
+
+        fn ready_state(&self) -> u16 {
+            WebSocket::OPEN
+        }
+
+        fn reconnect(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_url(&self, _url: String) -> Result<()> {
+            Ok(())
+        }
+
+        fn close(&self) {}
+    }
+
+    fn json_rpc() -> JsonRpc {
+        JsonRpc::new(
+            SilentTransport,
+            None,
+            DEFAULT_CHANNEL_CAPACITY,
+            DEFAULT_MAX_MESSAGE_SIZE,
+        )
+    }
+
+    #[wasm_bindgen_test]
+    async fn timed_out_requests_leave_nothing_pending() {
+        let json_rpc = json_rpc();
+        let requests = (0..100).map(|_| {
+            json_rpc.send_message_with_timeout::<Value>(
+                "ping".into(),
+                None,
+                Duration::from_millis(10),
+            )
+        });
+        for res in future::join_all(requests).await {
+            let error = res.unwrap_err();
+            assert!(error.downcast_ref::<EnforceTimeoutError>().is_some());
+        }
+        assert_eq!(json_rpc.pending_requests(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    async fn timed_out_batches_leave_nothing_pending() {
+        let json_rpc = json_rpc();
+        let requests = (0..10).map(|_| ("ping".to_string(), None)).collect();
+        let error = json_rpc
+            .send_batch(requests, Duration::from_millis(10))
+            .await
+            .unwrap_err();
+        assert!(error.downcast_ref::<EnforceTimeoutError>().is_some());
+        assert_eq!(json_rpc.pending_requests(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn dropped_requests_leave_nothing_pending() {
+        let json_rpc = json_rpc();
+        let mut request = Box::pin(json_rpc.send_message_with_timeout::<Value>(
+            "ping".into(),
+            None,
+            Duration::from_secs(30),
+        ));
+        assert!((&mut request).now_or_never().is_none());
+        assert_eq!(json_rpc.pending_requests(), 1);
+        drop(request);
+        assert_eq!(json_rpc.pending_requests(), 0);
+    }
+}
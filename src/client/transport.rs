@@ -1,75 +1,59 @@
 use anyhow::Result;
-use js_sys::Function;
 use thiserror::Error;
-use web_sys::WebSocket;
+
+#[cfg(test)]
+pub mod memory;
+pub mod webrtc;
+pub mod websocket;
 
 #[derive(Debug, Error)]
 pub enum TransportError {
     #[error("cannot create websocket with url `{0}` due to `{1}`")]
     CreatingWebsocket(String, String),
+    #[error("invalid websocket, `{0}` is not a function")]
+    InvalidWebsocket(String),
     #[error("sending message failed due to `{0}`")]
     SendingMessage(String),
+    #[error("connection cannot be reconnected")]
+    ReconnectionNotSupported,
+    #[error("connection was closed")]
+    Closed,
     #[allow(dead_code)]
     #[error("cannot add event listener with callback due to `{0}`")]
     AddingEventListener(String),
 }
-pub struct Transport {
-    websocket: WebSocket,
-}
 
-impl Transport {
-    /// Creates a new `Transport`.
-    pub fn new(url: String) -> Result<Self> {
-        let websocket = WebSocket::new(&url).map_err(|e| {
-            TransportError::CreatingWebsocket(url, e.as_string().unwrap_or("unknown error".into()))
-        })?;
-        Ok(Self { websocket })
-    }
+/// Connection used to exchange messages with the host.
+///
+/// Ready states follow the `WebSocket` constants (`CONNECTING`, `OPEN`,
+/// `CLOSING` and `CLOSED`).
+pub trait Transport {
+    /// Sends a new message.
+    fn send(&self, message: &str) -> Result<()>;
 
-    /// Sets closure to execute when a message is received on the websocket.
-    pub fn set_onmessage(&self, function: &Function) {
-        self.websocket.set_onmessage(Some(function));
-    }
+    /// Sets callback to execute when a message is received.
+    fn set_onmessage(&self, callback: Box<dyn FnMut(String)>);
 
-    /// Sets closure to execute when the websocket is open.
-    pub fn set_onopen(&self, function: &Function) {
-        self.websocket.set_onopen(Some(function));
-    }
+    /// Sets callback to execute when the connection is open.
+    fn set_onopen(&self, callback: Box<dyn FnMut()>);
 
-    /// Sets closure to execute when the websocket errors out.
-    #[allow(dead_code)]
-    pub fn set_onerror(&self, function: &Function) {
-        self.websocket.set_onerror(Some(function));
-    }
+    /// Sets callback to execute when the connection errors out.
+    fn set_onerror(&self, callback: Box<dyn FnMut()>);
 
-    /// Sets closure to execute when the websocket is closed.
-    #[allow(dead_code)]
-    pub fn set_onclose(&self, function: &Function) {
-        self.websocket.set_onclose(Some(function));
-    }
+    /// Sets callback to execute when the connection is closed, receiving
+    /// the close code and reason.
+    fn set_onclose(&self, callback: Box<dyn FnMut(u16, String)>);
 
-    /// Sends a new message to the websocket.
-    #[allow(dead_code)]
-    pub fn send(&self, message: &str) -> Result<()> {
-        self.websocket.send_with_str(message).map_err(|e| {
-            TransportError::SendingMessage(e.as_string().unwrap_or("unknown error".into()))
-        })?;
-        Ok(())
-    }
+    /// Returns the ready state of the connection.
+    fn ready_state(&self) -> u16;
 
-    /// Returns raw websocket object.
-    pub fn get_raw(&self) -> WebSocket {
-        self.websocket.clone()
-    }
+    /// Closes the connection and opens a new one to the same host, keeping
+    /// the registered callbacks. Completion is signaled by the open callback.
+    fn reconnect(&self) -> Result<()>;
 
-    /// Adds a new event listener with callback.
-    #[allow(dead_code)]
-    pub fn add_event_listener_with_callback(&self, event: &str, callback: &Function) -> Result<()> {
-        self.websocket
-            .add_event_listener_with_callback(event, callback)
-            .map_err(|e| {
-                TransportError::AddingEventListener(e.as_string().unwrap_or("unknown error".into()))
-            })?;
-        Ok(())
-    }
+    /// Changes the url to connect to, used from the next reconnection on.
+    fn set_url(&self, url: String) -> Result<()>;
+
+    /// Closes the connection for good, without reconnecting.
+    fn close(&self);
 }
@@ -0,0 +1,470 @@
+use super::{Transport, TransportError};
+use crate::client::json_rpc::JsonRpc;
+use anyhow::Result;
+use futures::{channel::mpsc, StreamExt};
+use json_rpc_types::{ErrorCode, Request, Response, Version};
+use mpc_manager::{
+    service::{
+        group_service::{GroupCreateRequest, GroupJoinRequest, GroupMethod},
+        session_service::{
+            SessionCreateRequest, SessionCreatedNotification, SessionEvent, SessionLoginRequest,
+            SessionMessageNotification, SessionMessageRequest, SessionMethod,
+            SessionReadyNotification, SessionSignupRequest,
+        },
+    },
+    state::session::SessionKind,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
+    rc::{Rc, Weak},
+};
+use uuid::Uuid;
+use web_sys::WebSocket;
+
+#[derive(Default)]
+struct Handlers {
+    onmessage: Option<Box<dyn FnMut(String)>>,
+    onopen: Option<Box<dyn FnMut()>>,
+    onclose: Option<Box<dyn FnMut(u16, String)>>,
+}
+
+/// In-memory transport connected to a `MemoryHub` in the same process.
+///
+/// Messages are delivered asynchronously, as they would be by a websocket,
+/// so it can be used to run the protocols against an in-process host
+/// without any network.
+pub struct MemoryTransport {
+    tx: mpsc::UnboundedSender<String>,
+    handlers: Rc<RefCell<Handlers>>,
+    ready_state: Rc<Cell<u16>>,
+}
+
+impl MemoryTransport {
+    fn new(tx: mpsc::UnboundedSender<String>, rx: mpsc::UnboundedReceiver<String>) -> Self {
+        let handlers = Rc::new(RefCell::new(Handlers::default()));
+        let ready_state = Rc::new(Cell::new(WebSocket::CONNECTING));
+        wasm_bindgen_futures::spawn_local(MemoryTransport::receive_task(
+            Rc::downgrade(&handlers),
+            ready_state.clone(),
+            rx,
+        ));
+        Self {
+            tx,
+            handlers,
+            ready_state,
+        }
+    }
+
+    /// Delivers received messages to the `onmessage` callback until the hub
+    /// drops the connection. As it runs after the transport is created, the
+    /// callbacks registered by then get the open event too.
+    async fn receive_task(
+        handlers: Weak<RefCell<Handlers>>,
+        ready_state: Rc<Cell<u16>>,
+        mut rx: mpsc::UnboundedReceiver<String>,
+    ) {
+        ready_state.set(WebSocket::OPEN);
+        if let Some(handlers) = handlers.upgrade() {
+            let mut handlers = handlers.borrow_mut();
+            if let Some(onopen) = handlers.onopen.as_mut() {
+                onopen();
+            }
+        }
+
+        while let Some(message) = rx.next().await {
+            let Some(handlers) = handlers.upgrade() else { return };
+            let mut handlers = handlers.borrow_mut();
+            if let Some(onmessage) = handlers.onmessage.as_mut() {
+                onmessage(message);
+            }
+        }
+
+        ready_state.set(WebSocket::CLOSED);
+        let Some(handlers) = handlers.upgrade() else { return };
+        let mut handlers = handlers.borrow_mut();
+        if let Some(onclose) = handlers.onclose.as_mut() {
+            onclose(1000, "hub dropped".into());
+        }
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&self, message: &str) -> Result<()> {
+        self.tx
+            .unbounded_send(message.into())
+            .map_err(|_| TransportError::SendingMessage("hub dropped".into()))?;
+        Ok(())
+    }
+
+    fn set_onmessage(&self, callback: Box<dyn FnMut(String)>) {
+        self.handlers.borrow_mut().onmessage = Some(callback);
+    }
+
+    fn set_onopen(&self, callback: Box<dyn FnMut()>) {
+        self.handlers.borrow_mut().onopen = Some(callback);
+    }
+
+    /// An in-memory connection never errors out, so the callback is ignored.
+    fn set_onerror(&self, _callback: Box<dyn FnMut()>) {}
+
+    fn set_onclose(&self, callback: Box<dyn FnMut(u16, String)>) {
+        self.handlers.borrow_mut().onclose = Some(callback);
+    }
+
+    fn ready_state(&self) -> u16 {
+        self.ready_state.get()
+    }
+
+    /// The hub forgets a connection once closed, so it always fails.
+    fn reconnect(&self) -> Result<()> {
+        Err(TransportError::ReconnectionNotSupported.into())
+    }
+
+    fn set_url(&self, _url: String) -> Result<()> {
+        Err(TransportError::ReconnectionNotSupported.into())
+    }
+
+    /// Ends the channel to the hub, which sees it as a closed connection.
+    fn close(&self) {
+        self.tx.close_channel();
+        self.ready_state.set(WebSocket::CLOSED);
+    }
+}
+
+type ConnectionId = u64;
+
+type HubResult = std::result::Result<Value, json_rpc_types::Error<Value>>;
+
+/// In-process host answering the requests of the `MemoryTransport`s
+/// connected to it as the manager does: it keeps groups and sessions,
+/// assigns party numbers and relays the messages of each party of a session
+/// to the others, or to their receiver only.
+///
+/// It lets several `MultiPartyEcdsa` instances run the protocols together in
+/// a single process, without any network.
+#[derive(Clone, Default)]
+pub struct MemoryHub {
+    state: Rc<RefCell<HubState>>,
+}
+
+#[derive(Default)]
+struct HubState {
+    connections: HashMap<ConnectionId, mpsc::UnboundedSender<String>>,
+    next_connection: ConnectionId,
+    groups: HashMap<Uuid, GroupState>,
+}
+
+struct GroupState {
+    /// Parameters as sent by the creator, `{ n, t }`.
+    params: Value,
+    members: HashSet<ConnectionId>,
+    sessions: HashMap<Uuid, SessionState>,
+}
+
+struct SessionState {
+    /// Session as returned to the clients, `{ id, kind, value }`.
+    session: Value,
+    kind: SessionKind,
+    /// Connection of every party signed up or logged in.
+    parties: BTreeMap<u16, ConnectionId>,
+}
+
+impl MemoryHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens a new connection to the hub.
+    pub fn connect(&self) -> MemoryTransport {
+        let (client_tx, client_rx) = mpsc::unbounded::<String>();
+        let (hub_tx, mut hub_rx) = mpsc::unbounded::<String>();
+        let connection = {
+            let mut state = self.state.borrow_mut();
+            let connection = state.next_connection;
+            state.next_connection += 1;
+            state.connections.insert(connection, client_tx);
+            connection
+        };
+
+        // Handles the messages of the connection until it's closed
+        let state = self.state.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            while let Some(message) = hub_rx.next().await {
+                state.borrow_mut().handle(connection, &message);
+            }
+            state.borrow_mut().connections.remove(&connection);
+        });
+        MemoryTransport::new(hub_tx, client_rx)
+    }
+}
+
+impl HubState {
+    fn handle(&mut self, connection: ConnectionId, message: &str) {
+        let Ok(request) = serde_json::from_str::<Request<Value>>(message) else {
+            log::warn!("Hub received an invalid request: {}", message);
+            return;
+        };
+        let method = request.method.as_str().to_owned();
+        let params = request.params.unwrap_or(Value::Null);
+        if request.id.is_none() {
+            if method == SessionMethod::SessionMessage.to_string() {
+                if let Ok(params) = parse(params) {
+                    self.relay(connection, params);
+                }
+            }
+            return;
+        }
+
+        let result = if method == GroupMethod::GroupCreate.to_string() {
+            parse(params).and_then(|params| self.group_create(connection, params))
+        } else if method == GroupMethod::GroupJoin.to_string() {
+            parse(params).and_then(|params| self.group_join(connection, params))
+        } else if method == SessionMethod::SessionCreate.to_string() {
+            parse(params).and_then(|params| self.session_create(connection, params))
+        } else if method == SessionMethod::SessionSignup.to_string() {
+            parse(params).and_then(|params| self.session_signup(connection, params))
+        } else if method == SessionMethod::SessionLogin.to_string() {
+            parse(params).and_then(|params| self.session_login(connection, params))
+        } else {
+            Err(json_rpc_types::Error::from_code(ErrorCode::MethodNotFound))
+        };
+        let response: Response<Value, Value> = match result {
+            Ok(result) => Response::result(Version::V2, result, request.id),
+            Err(error) => Response::error(Version::V2, error, request.id),
+        };
+        self.send(connection, &response);
+    }
+
+    fn group_create(&mut self, connection: ConnectionId, request: GroupCreateRequest) -> HubResult {
+        let params = serde_json::to_value(request.parameters).map_err(|_| invalid_params())?;
+        let group_id = Uuid::new_v4();
+        self.groups.insert(
+            group_id,
+            GroupState {
+                params: params.clone(),
+                members: HashSet::from([connection]),
+                sessions: HashMap::new(),
+            },
+        );
+        Ok(json!({ "group": { "id": group_id, "params": params } }))
+    }
+
+    fn group_join(&mut self, connection: ConnectionId, request: GroupJoinRequest) -> HubResult {
+        let group = self
+            .groups
+            .get_mut(&request.group_id)
+            .ok_or_else(|| error("group not found"))?;
+        if !group.members.contains(&connection) && group.members.len() >= group.parties() {
+            return Err(error("group is full"));
+        }
+        group.members.insert(connection);
+        Ok(json!({ "group": { "id": request.group_id, "params": group.params } }))
+    }
+
+    fn session_create(
+        &mut self,
+        connection: ConnectionId,
+        request: SessionCreateRequest,
+    ) -> HubResult {
+        let group = self.member_group(connection, request.group_id)?;
+        let session_id = Uuid::new_v4();
+        let session = json!({
+            "id": session_id,
+            "kind": request.kind,
+            "value": request.value,
+        });
+        group.sessions.insert(
+            session_id,
+            SessionState {
+                session: session.clone(),
+                kind: request.kind,
+                parties: BTreeMap::new(),
+            },
+        );
+
+        // Tells every member of the group, as the manager does
+        let members: Vec<ConnectionId> = group.members.iter().copied().collect();
+        if let Ok(session) = serde_json::from_value(session.clone()) {
+            let notification = SessionCreatedNotification {
+                group_id: request.group_id,
+                session,
+            };
+            self.notify(
+                &members,
+                SessionEvent::SessionCreated.to_string(),
+                notification,
+            );
+        }
+        Ok(json!({ "session": session }))
+    }
+
+    fn session_signup(
+        &mut self,
+        connection: ConnectionId,
+        request: SessionSignupRequest,
+    ) -> HubResult {
+        let group = self.member_group(connection, request.group_id)?;
+        let parties = group.parties();
+        let session = group
+            .sessions
+            .get_mut(&request.session_id)
+            .ok_or_else(|| error("session not found"))?;
+        if session.parties.values().any(|c| *c == connection) {
+            return Err(error("party already signed up to the session"));
+        }
+        let party_number = (1..=parties as u16)
+            .find(|party| !session.parties.contains_key(party))
+            .ok_or_else(|| error("session is full"))?;
+        session.parties.insert(party_number, connection);
+        let response = json!({ "session": session.session, "partyNumber": party_number });
+        self.notify_if_ready(request.group_id, request.session_id);
+        Ok(response)
+    }
+
+    fn session_login(
+        &mut self,
+        connection: ConnectionId,
+        request: SessionLoginRequest,
+    ) -> HubResult {
+        let group = self.member_group(connection, request.group_id)?;
+        if request.party_number == 0 || usize::from(request.party_number) > group.parties() {
+            return Err(invalid_params());
+        }
+        let session = group
+            .sessions
+            .get_mut(&request.session_id)
+            .ok_or_else(|| error("session not found"))?;
+        // Logging in again replaces the connection of the party, so it can
+        // resume the session after reconnecting
+        let joined = session
+            .parties
+            .insert(request.party_number, connection)
+            .is_none();
+        let response = json!({ "session": session.session });
+        if joined {
+            self.notify_if_ready(request.group_id, request.session_id);
+        }
+        Ok(response)
+    }
+
+    /// Sends a session message to its receiver, or to every other party of
+    /// the session if it has none, tagged with the party number of its
+    /// sender.
+    fn relay(&mut self, connection: ConnectionId, request: SessionMessageRequest<Value>) {
+        let Some(session) = self
+            .groups
+            .get(&request.group_id)
+            .and_then(|group| group.sessions.get(&request.session_id))
+        else {
+            return;
+        };
+        let Some(sender) = session
+            .parties
+            .iter()
+            .find_map(|(party, c)| (*c == connection).then_some(*party))
+        else {
+            log::warn!("Hub dropping message of a connection not in the session");
+            return;
+        };
+        let receivers: Vec<ConnectionId> = session
+            .parties
+            .iter()
+            .filter(|(party, _)| match request.receiver {
+                Some(receiver) => **party == receiver,
+                None => **party != sender,
+            })
+            .map(|(_, c)| *c)
+            .collect();
+        let notification = SessionMessageNotification {
+            group_id: request.group_id,
+            session_id: request.session_id,
+            sender,
+            message: request.message,
+        };
+        self.notify(
+            &receivers,
+            SessionMethod::SessionMessage.to_string(),
+            notification,
+        );
+    }
+
+    /// Tells the parties of a session it's ready once all of them joined:
+    /// every party of the group for keygen, or one more than the threshold
+    /// for signing.
+    fn notify_if_ready(&mut self, group_id: Uuid, session_id: Uuid) {
+        let Some(group) = self.groups.get(&group_id) else { return };
+        let Some(session) = group.sessions.get(&session_id) else { return };
+        let required = match session.kind {
+            SessionKind::Keygen => group.parties(),
+            SessionKind::Sign => group.threshold() + 1,
+        };
+        if session.parties.len() != required {
+            return;
+        }
+        let parties: Vec<ConnectionId> = session.parties.values().copied().collect();
+        let notification = SessionReadyNotification {
+            group_id,
+            session_id,
+        };
+        self.notify(
+            &parties,
+            SessionEvent::SessionReady.to_string(),
+            notification,
+        );
+    }
+
+    fn member_group(
+        &mut self,
+        connection: ConnectionId,
+        group_id: Uuid,
+    ) -> std::result::Result<&mut GroupState, json_rpc_types::Error<Value>> {
+        let group = self
+            .groups
+            .get_mut(&group_id)
+            .ok_or_else(|| error("group not found"))?;
+        if !group.members.contains(&connection) {
+            return Err(error("not a member of the group"));
+        }
+        Ok(group)
+    }
+
+    fn notify<T: Serialize>(&self, connections: &[ConnectionId], method: String, params: T) {
+        let notification = JsonRpc::new_request(None, method, Some(params));
+        for connection in connections {
+            self.send(*connection, &notification);
+        }
+    }
+
+    fn send<T: Serialize>(&self, connection: ConnectionId, message: &T) {
+        let Some(tx) = self.connections.get(&connection) else { return };
+        let Ok(message) = serde_json::to_string(message) else { return };
+        let _ = tx.unbounded_send(message); // Ignores closed connection
+    }
+}
+
+impl GroupState {
+    fn parties(&self) -> usize {
+        self.params["n"].as_u64().unwrap_or_default() as usize
+    }
+
+    fn threshold(&self) -> usize {
+        self.params["t"].as_u64().unwrap_or_default() as usize
+    }
+}
+
+fn parse<T: DeserializeOwned>(
+    params: Value,
+) -> std::result::Result<T, json_rpc_types::Error<Value>> {
+    serde_json::from_value(params).map_err(|_| invalid_params())
+}
+
+fn error(message: &str) -> json_rpc_types::Error<Value> {
+    json_rpc_types::Error::with_custom_msg(ErrorCode::InvalidParams, message)
+}
+
+fn invalid_params() -> json_rpc_types::Error<Value> {
+    json_rpc_types::Error::from_code(ErrorCode::InvalidParams)
+}
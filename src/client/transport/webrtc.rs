@@ -0,0 +1,176 @@
+use super::{Transport, TransportError};
+use anyhow::Result;
+use js_sys::JsString;
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+use wasm_bindgen::{prelude::Closure, JsCast};
+use web_sys::{Event, MessageEvent, RtcDataChannel, RtcDataChannelState, WebSocket};
+
+/// Callbacks registered by the user of the transport.
+#[derive(Default)]
+struct Handlers {
+    onmessage: Option<Closure<dyn FnMut(MessageEvent)>>,
+    onopen: Option<Closure<dyn FnMut(Event)>>,
+    onerror: Option<Closure<dyn FnMut(Event)>>,
+    onclose: Option<Closure<dyn FnMut(Event)>>,
+}
+
+impl Handlers {
+    /// Registers the handlers on the given data channel.
+    fn apply(&self, channel: &RtcDataChannel) {
+        channel.set_onmessage(self.onmessage.as_ref().map(|c| c.as_ref().unchecked_ref()));
+        channel.set_onopen(self.onopen.as_ref().map(|c| c.as_ref().unchecked_ref()));
+        channel.set_onerror(self.onerror.as_ref().map(|c| c.as_ref().unchecked_ref()));
+        channel.set_onclose(self.onclose.as_ref().map(|c| c.as_ref().unchecked_ref()));
+    }
+}
+
+struct TransportState {
+    channel: RtcDataChannel,
+    handlers: Handlers,
+    outgoing: VecDeque<String>,
+    flush: Option<Closure<dyn FnMut(Event)>>,
+}
+
+impl Drop for TransportState {
+    /// Detaches the listeners before they are dropped and closes the channel.
+    fn drop(&mut self) {
+        Handlers::default().apply(&self.channel);
+        if let Some(flush) = self.flush.as_ref() {
+            let _ = self
+                .channel
+                .remove_event_listener_with_callback("open", flush.as_ref().unchecked_ref());
+        }
+        self.channel.close();
+    }
+}
+
+/// Transport over a WebRTC data channel, so protocol messages can be
+/// exchanged with a peer directly instead of through a central websocket.
+/// Messages keep the same JSON-RPC framing, so the peer must answer them
+/// as the manager would.
+///
+/// The channel is created and negotiated by the caller, so it's not
+/// reconnected when closed. Messages sent before it opens are buffered.
+pub struct DataChannelTransport {
+    state: Rc<RefCell<TransportState>>,
+}
+
+impl DataChannelTransport {
+    /// Creates a new `DataChannelTransport` over an already created data
+    /// channel, whether it's open yet or not.
+    pub fn new(channel: RtcDataChannel) -> Self {
+        let state = Rc::new(RefCell::new(TransportState {
+            channel,
+            handlers: Handlers::default(),
+            outgoing: VecDeque::new(),
+            flush: None,
+        }));
+
+        // Flushes the buffered messages once the channel opens, ahead of the
+        // user's open callback as it's registered first
+        let state_c = Rc::downgrade(&state);
+        let flush = Closure::<dyn FnMut(_)>::new(move |_: Event| {
+            let Some(state) = state_c.upgrade() else { return };
+            let mut state = state.borrow_mut();
+            let outgoing = std::mem::take(&mut state.outgoing);
+            for message in outgoing {
+                if let Err(e) = state.channel.send_with_str(&message) {
+                    log::warn!("Failed to send buffered message: {:?}", e);
+                }
+            }
+        });
+        {
+            let mut state = state.borrow_mut();
+            if let Err(e) = state
+                .channel
+                .add_event_listener_with_callback("open", flush.as_ref().unchecked_ref())
+            {
+                log::warn!("Failed to listen for data channel open: {:?}", e);
+            }
+            state.flush = Some(flush);
+        }
+
+        Self { state }
+    }
+}
+
+impl Transport for DataChannelTransport {
+    /// Sends a new message through the data channel, buffering it if the
+    /// channel is not open yet.
+    fn send(&self, message: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        match state.channel.ready_state() {
+            RtcDataChannelState::Connecting => {
+                state.outgoing.push_back(message.into());
+                Ok(())
+            }
+            RtcDataChannelState::Open => {
+                state.channel.send_with_str(message).map_err(|e| {
+                    TransportError::SendingMessage(e.as_string().unwrap_or("unknown error".into()))
+                })?;
+                Ok(())
+            }
+            _ => Err(TransportError::SendingMessage("data channel is closed".into()).into()),
+        }
+    }
+
+    fn set_onmessage(&self, mut callback: Box<dyn FnMut(String)>) {
+        let closure = Closure::<dyn FnMut(_)>::new(move |message: MessageEvent| {
+            // Check message is string otherwise ignore it
+            let Ok(message) = message.data().dyn_into::<JsString>() else { return };
+            callback(String::from(message));
+        });
+        let mut state = self.state.borrow_mut();
+        state.handlers.onmessage = Some(closure);
+        state.handlers.apply(&state.channel);
+    }
+
+    fn set_onopen(&self, mut callback: Box<dyn FnMut()>) {
+        let closure = Closure::<dyn FnMut(_)>::new(move |_: Event| callback());
+        let mut state = self.state.borrow_mut();
+        state.handlers.onopen = Some(closure);
+        state.handlers.apply(&state.channel);
+    }
+
+    fn set_onerror(&self, mut callback: Box<dyn FnMut()>) {
+        let closure = Closure::<dyn FnMut(_)>::new(move |_: Event| callback());
+        let mut state = self.state.borrow_mut();
+        state.handlers.onerror = Some(closure);
+        state.handlers.apply(&state.channel);
+    }
+
+    /// Data channels don't report why they were closed, so the callback
+    /// always gets a normal closure code and an empty reason.
+    fn set_onclose(&self, mut callback: Box<dyn FnMut(u16, String)>) {
+        let closure = Closure::<dyn FnMut(_)>::new(move |_: Event| callback(1000, String::new()));
+        let mut state = self.state.borrow_mut();
+        state.handlers.onclose = Some(closure);
+        state.handlers.apply(&state.channel);
+    }
+
+    /// Returns the ready state of the data channel, mapped to the
+    /// equivalent `WebSocket` constant.
+    fn ready_state(&self) -> u16 {
+        match self.state.borrow().channel.ready_state() {
+            RtcDataChannelState::Connecting => WebSocket::CONNECTING,
+            RtcDataChannelState::Open => WebSocket::OPEN,
+            RtcDataChannelState::Closing => WebSocket::CLOSING,
+            _ => WebSocket::CLOSED,
+        }
+    }
+
+    /// The channel is negotiated by the caller, so it always fails.
+    fn reconnect(&self) -> Result<()> {
+        Err(TransportError::ReconnectionNotSupported.into())
+    }
+
+    fn set_url(&self, _url: String) -> Result<()> {
+        Err(TransportError::ReconnectionNotSupported.into())
+    }
+
+    fn close(&self) {
+        let mut state = self.state.borrow_mut();
+        state.outgoing.clear();
+        state.channel.close();
+    }
+}
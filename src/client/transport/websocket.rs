@@ -0,0 +1,469 @@
+use super::{Transport, TransportError};
+use crate::utils::timeout::timeout;
+use anyhow::Result;
+use futures::{channel::mpsc, StreamExt};
+use js_sys::{Function, JsString, Reflect};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    rc::{Rc, Weak},
+    time::Duration,
+};
+use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
+use web_sys::{CloseEvent, CloseEventInit, Event, MessageEvent, WebSocket};
+
+/// Code of the close event fired when reconnection is given up, as for
+/// connections closed abnormally.
+const GIVE_UP_CLOSE_CODE: u16 = 1006;
+const GIVE_UP_CLOSE_REASON: &str = "max reconnect attempts exceeded";
+
+/// Policy used to reconnect the websocket after it gets closed.
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    /// Maximum number of consecutive reconnection attempts, unlimited if
+    /// `None`. Once exceeded the transport is closed for good.
+    pub max_retries: Option<u32>,
+    /// Delay before the first reconnection attempt.
+    pub base_delay: Duration,
+    /// Upper bound of the delay between reconnection attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: Some(10),
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Returns the delay to wait before the given attempt (starting at 0),
+    /// doubling it on every attempt up to `max_delay`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Returns whether the given attempt (starting at 1) exceeds the
+    /// maximum, so reconnection must be given up.
+    pub fn gives_up(&self, attempt: u32) -> bool {
+        self.max_retries.is_some_and(|max| attempt > max)
+    }
+}
+
+/// Callbacks registered by the user of the transport. They are kept
+/// so they can be registered again on every new websocket.
+#[derive(Default)]
+struct Handlers {
+    onmessage: Option<Closure<dyn FnMut(MessageEvent)>>,
+    onopen: Option<Closure<dyn FnMut(Event)>>,
+    onerror: Option<Closure<dyn FnMut(Event)>>,
+    onclose: Option<Closure<dyn FnMut(CloseEvent)>>,
+}
+
+impl Handlers {
+    /// Registers the handlers on the given websocket.
+    fn apply(&self, websocket: &WebSocket) {
+        websocket.set_onmessage(self.onmessage.as_ref().map(|c| c.as_ref().unchecked_ref()));
+        websocket.set_onopen(self.onopen.as_ref().map(|c| c.as_ref().unchecked_ref()));
+        websocket.set_onerror(self.onerror.as_ref().map(|c| c.as_ref().unchecked_ref()));
+        websocket.set_onclose(self.onclose.as_ref().map(|c| c.as_ref().unchecked_ref()));
+    }
+}
+
+/// Reason to open a new websocket.
+enum ReconnectReason {
+    /// The current websocket was closed.
+    Closed,
+    /// A reconnection was requested, so the current websocket must be closed.
+    Requested,
+}
+
+struct TransportState {
+    url: Option<String>,
+    websocket: WebSocket,
+    handlers: Handlers,
+    outgoing: VecDeque<String>,
+    attempts: u32,
+    closed: bool,
+    reconnect_tx: mpsc::UnboundedSender<ReconnectReason>,
+}
+
+impl Drop for TransportState {
+    /// Detaches the handlers before they are dropped and closes the websocket.
+    /// The internal listeners are kept until the close event stops the
+    /// reconnection task, which drops them.
+    fn drop(&mut self) {
+        Handlers::default().apply(&self.websocket);
+        if let Err(e) = self.websocket.close() {
+            log::warn!("Failed to close websocket: {:?}", e);
+        }
+    }
+}
+
+/// Websocket transport that reconnects with exponential backoff when the
+/// connection is closed. Messages sent while the websocket is not open are
+/// buffered and flushed once the connection is (re)established.
+#[derive(Clone)]
+pub struct WebSocketTransport {
+    state: Rc<RefCell<TransportState>>,
+}
+
+impl WebSocketTransport {
+    /// Creates a new `WebSocketTransport`.
+    pub fn new(url: String, policy: ReconnectPolicy) -> Result<Self> {
+        let websocket = WebSocketTransport::create_websocket(&url)?;
+        Ok(WebSocketTransport::with_websocket(
+            websocket,
+            Some(url),
+            policy,
+        ))
+    }
+
+    /// Creates a new `WebSocketTransport` from an already constructed
+    /// websocket, which can be any object implementing the `WebSocket`
+    /// interface (e.g. the `ws` package in Node.js).
+    ///
+    /// As the websocket cannot be recreated, it's not reconnected when closed.
+    pub fn from_websocket(websocket: JsValue) -> Result<Self> {
+        for method in ["send", "addEventListener"] {
+            let is_function = Reflect::get(&websocket, &method.into())
+                .map(|value| value.is_function())
+                .unwrap_or(false);
+            if !is_function {
+                return Err(TransportError::InvalidWebsocket(method.into()).into());
+            }
+        }
+        Ok(WebSocketTransport::with_websocket(
+            websocket.unchecked_into(),
+            None,
+            ReconnectPolicy::default(),
+        ))
+    }
+
+    fn with_websocket(websocket: WebSocket, url: Option<String>, policy: ReconnectPolicy) -> Self {
+        let (close_tx, close_rx) = mpsc::unbounded::<ReconnectReason>();
+        let state = Rc::new(RefCell::new(TransportState {
+            url,
+            websocket,
+            handlers: Handlers::default(),
+            outgoing: VecDeque::new(),
+            attempts: 0,
+            closed: false,
+            reconnect_tx: close_tx.clone(),
+        }));
+
+        // Internal listeners, attached to every websocket created
+        let state_c = Rc::downgrade(&state);
+        let onopen = Closure::<dyn FnMut(_)>::new(move |_: Event| {
+            let Some(state) = state_c.upgrade() else { return };
+            let mut state = state.borrow_mut();
+            state.attempts = 0;
+            while let Some(message) = state.outgoing.pop_front() {
+                if let Err(e) = state.websocket.send_with_str(&message) {
+                    log::error!("Failed to flush buffered message: {:?}", e);
+                }
+            }
+        });
+        let close_tx_c = close_tx.clone();
+        let state_c = Rc::downgrade(&state);
+        let onclose = Closure::<dyn FnMut(_)>::new(move |event: Event| {
+            // Websockets replaced by a requested reconnection or closed for
+            // good are not reconnected
+            if let Some(state) = state_c.upgrade() {
+                let state = state.borrow();
+                let websocket: JsValue = state.websocket.clone().into();
+                if state.closed || event.target().map(JsValue::from) != Some(websocket) {
+                    return;
+                }
+            }
+            let _ = close_tx_c.unbounded_send(ReconnectReason::Closed); // Ignores stopped reconnection task
+        });
+        WebSocketTransport::attach_listeners(&state.borrow().websocket, &onopen, &onclose);
+
+        wasm_bindgen_futures::spawn_local(WebSocketTransport::reconnect_task(
+            Rc::downgrade(&state),
+            policy,
+            close_tx,
+            close_rx,
+            onopen,
+            onclose,
+        ));
+
+        Self { state }
+    }
+
+    /// Returns raw websocket object.
+    #[allow(dead_code)]
+    pub fn get_raw(&self) -> WebSocket {
+        self.state.borrow().websocket.clone()
+    }
+
+    /// Adds a new event listener with callback.
+    #[allow(dead_code)]
+    pub fn add_event_listener_with_callback(&self, event: &str, callback: &Function) -> Result<()> {
+        self.state
+            .borrow()
+            .websocket
+            .add_event_listener_with_callback(event, callback)
+            .map_err(|e| {
+                TransportError::AddingEventListener(e.as_string().unwrap_or("unknown error".into()))
+            })?;
+        Ok(())
+    }
+
+    /// Waits for close events and recreates the websocket using exponential
+    /// backoff. Stops when the transport is dropped or when the maximum number
+    /// of retries is reached.
+    ///
+    /// Requested reconnections replace the websocket right away, closing the
+    /// previous one.
+    async fn reconnect_task(
+        state: Weak<RefCell<TransportState>>,
+        policy: ReconnectPolicy,
+        close_tx: mpsc::UnboundedSender<ReconnectReason>,
+        mut close_rx: mpsc::UnboundedReceiver<ReconnectReason>,
+        onopen: Closure<dyn FnMut(Event)>,
+        onclose: Closure<dyn FnMut(Event)>,
+    ) {
+        while let Some(reason) = close_rx.next().await {
+            let requested = matches!(reason, ReconnectReason::Requested);
+            let Some((attempt, url)) = state.upgrade().map(|state| {
+                let mut state = state.borrow_mut();
+                state.attempts = if requested { 1 } else { state.attempts + 1 };
+                (state.attempts, state.url.clone())
+            }) else {
+                break;
+            };
+            let Some(url) = url else {
+                log::error!("Connection closed, websockets created outside the library cannot be reconnected");
+                break;
+            };
+            if policy.gives_up(attempt) {
+                log::error!("Giving up reconnection after {} attempts", attempt - 1);
+                if let Some(state) = state.upgrade() {
+                    WebSocketTransport::give_up(&state);
+                }
+                break;
+            }
+
+            if !requested {
+                let delay = policy.delay(attempt - 1);
+                log::warn!(
+                    "Connection closed, reconnecting in {}ms (attempt {})",
+                    delay.as_millis(),
+                    attempt
+                );
+                timeout(delay).await;
+            }
+
+            let Some(state) = state.upgrade() else { break };
+            if state.borrow().closed {
+                break;
+            }
+            let websocket = match WebSocketTransport::create_websocket(&url) {
+                Ok(websocket) => websocket,
+                Err(e) => {
+                    log::error!("Reconnection failed: {}", e);
+                    let _ = close_tx.unbounded_send(ReconnectReason::Closed);
+                    continue;
+                }
+            };
+            WebSocketTransport::attach_listeners(&websocket, &onopen, &onclose);
+
+            let mut state = state.borrow_mut();
+            state.handlers.apply(&websocket);
+            let previous = std::mem::replace(&mut state.websocket, websocket);
+            // The previous websocket keeps its handlers, so its close event
+            // fails the requests that were waiting for a response through it
+            if requested {
+                if let Err(e) = previous.close() {
+                    log::warn!("Failed to close previous websocket: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Closes the transport for good, dropping the buffered messages, and
+    /// fires a final close event so its user fails what's pending.
+    fn give_up(state: &RefCell<TransportState>) {
+        let onclose = {
+            let mut state = state.borrow_mut();
+            state.closed = true;
+            state.outgoing.clear();
+            state
+                .handlers
+                .onclose
+                .as_ref()
+                .map(|c| c.as_ref().unchecked_ref::<Function>().clone())
+        };
+        let Some(onclose) = onclose else { return };
+        let init = CloseEventInit::new();
+        init.set_code(GIVE_UP_CLOSE_CODE);
+        init.set_reason(GIVE_UP_CLOSE_REASON);
+        match CloseEvent::new_with_event_init_dict("close", &init) {
+            Ok(event) => {
+                if let Err(e) = onclose.call1(&JsValue::NULL, &event) {
+                    log::error!("Failed to fire close event: {:?}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to create close event: {:?}", e),
+        }
+    }
+
+    fn create_websocket(url: &str) -> Result<WebSocket> {
+        let websocket = WebSocket::new(url).map_err(|e| {
+            TransportError::CreatingWebsocket(
+                url.into(),
+                e.as_string().unwrap_or("unknown error".into()),
+            )
+        })?;
+        Ok(websocket)
+    }
+
+    fn attach_listeners(
+        websocket: &WebSocket,
+        onopen: &Closure<dyn FnMut(Event)>,
+        onclose: &Closure<dyn FnMut(Event)>,
+    ) {
+        let _ = websocket.add_event_listener_with_callback("open", onopen.as_ref().unchecked_ref());
+        let _ =
+            websocket.add_event_listener_with_callback("close", onclose.as_ref().unchecked_ref());
+    }
+}
+
+impl Transport for WebSocketTransport {
+    /// Sends a new message to the websocket.
+    ///
+    /// If the websocket is not open yet (or it's reconnecting) the message
+    /// is buffered and sent as soon as the connection is established.
+    fn send(&self, message: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        if state.closed {
+            return Err(TransportError::Closed.into());
+        }
+        if state.websocket.ready_state() != WebSocket::OPEN {
+            state.outgoing.push_back(message.into());
+            return Ok(());
+        }
+        state.websocket.send_with_str(message).map_err(|e| {
+            TransportError::SendingMessage(e.as_string().unwrap_or("unknown error".into()))
+        })?;
+        Ok(())
+    }
+
+    fn set_onmessage(&self, mut callback: Box<dyn FnMut(String)>) {
+        let closure = Closure::<dyn FnMut(_)>::new(move |message: MessageEvent| {
+            // Check message is string otherwise ignore it
+            let Ok(message) = message.data().dyn_into::<JsString>() else { return };
+            callback(String::from(message));
+        });
+        let mut state = self.state.borrow_mut();
+        state.handlers.onmessage = Some(closure);
+        state.handlers.apply(&state.websocket);
+    }
+
+    fn set_onopen(&self, mut callback: Box<dyn FnMut()>) {
+        let closure = Closure::<dyn FnMut(_)>::new(move |_: Event| callback());
+        let mut state = self.state.borrow_mut();
+        state.handlers.onopen = Some(closure);
+        state.handlers.apply(&state.websocket);
+    }
+
+    fn set_onerror(&self, mut callback: Box<dyn FnMut()>) {
+        let closure = Closure::<dyn FnMut(_)>::new(move |_: Event| callback());
+        let mut state = self.state.borrow_mut();
+        state.handlers.onerror = Some(closure);
+        state.handlers.apply(&state.websocket);
+    }
+
+    fn set_onclose(&self, mut callback: Box<dyn FnMut(u16, String)>) {
+        let closure = Closure::<dyn FnMut(_)>::new(move |event: CloseEvent| {
+            callback(event.code(), event.reason())
+        });
+        let mut state = self.state.borrow_mut();
+        state.handlers.onclose = Some(closure);
+        state.handlers.apply(&state.websocket);
+    }
+
+    /// Returns the ready state of the current websocket.
+    fn ready_state(&self) -> u16 {
+        self.state.borrow().websocket.ready_state()
+    }
+
+    /// Replaces the websocket with a new one to the same url, without waiting
+    /// for the reconnection delay. Messages sent meanwhile are buffered.
+    fn reconnect(&self) -> Result<()> {
+        let state = self.state.borrow();
+        if state.closed {
+            return Err(TransportError::Closed.into());
+        }
+        if state.url.is_none() {
+            return Err(TransportError::ReconnectionNotSupported.into());
+        }
+        let _ = state
+            .reconnect_tx
+            .unbounded_send(ReconnectReason::Requested); // Ignores stopped reconnection task
+        Ok(())
+    }
+
+    /// Replaces the url used to create new websockets. The current one is kept
+    /// until the next reconnection.
+    fn set_url(&self, url: String) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        if state.closed {
+            return Err(TransportError::Closed.into());
+        }
+        if state.url.is_none() {
+            return Err(TransportError::ReconnectionNotSupported.into());
+        }
+        state.url = Some(url);
+        Ok(())
+    }
+
+    /// Closes the websocket without reconnecting it, dropping the buffered
+    /// messages.
+    fn close(&self) {
+        let mut state = self.state.borrow_mut();
+        state.closed = true;
+        state.outgoing.clear();
+        if let Err(e) = state.websocket.close() {
+            log::warn!("Failed to close websocket: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_the_delay_up_to_the_maximum() {
+        let policy = ReconnectPolicy {
+            max_retries: Some(10),
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(5),
+        };
+        let delays: Vec<u128> = (0..6)
+            .map(|attempt| policy.delay(attempt).as_millis())
+            .collect();
+        assert_eq!(delays, [500, 1000, 2000, 4000, 5000, 5000]);
+        assert_eq!(policy.delay(u32::MAX), policy.max_delay);
+    }
+
+    #[test]
+    fn gives_up_after_the_maximum_attempts() {
+        let policy = ReconnectPolicy::default();
+        assert!((1..=10).all(|attempt| !policy.gives_up(attempt)));
+        assert!(policy.gives_up(11));
+
+        let unlimited = ReconnectPolicy {
+            max_retries: None,
+            ..policy
+        };
+        assert!(!unlimited.gives_up(u32::MAX));
+    }
+}
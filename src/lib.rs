@@ -1,3 +1,7 @@
+// Locks are synchronous and must never be held across await points, as a
+// task waiting on one would block the only thread running the others
+#![deny(clippy::await_holding_lock)]
+
 use wasm_bindgen::prelude::*;
 
 mod client;
@@ -5,6 +9,7 @@ mod multi_party_ecdsa;
 pub mod utils;
 
 // Required for rayon thread support
+#[cfg(feature = "thread-pool")]
 pub use wasm_bindgen_rayon::init_thread_pool;
 
 #[wasm_bindgen]
@@ -16,7 +21,7 @@ extern "C" {
 #[wasm_bindgen(start)]
 pub fn start() {
     console_error_panic_hook::set_once();
-    if wasm_log::try_init(wasm_log::Config::new(log::Level::Debug)).is_ok() {
+    if utils::logger::init(log::LevelFilter::Debug).is_ok() {
         log::info!("WASM logger initialized");
     }
     log::info!("WASM: module started {:?}", std::thread::current().id());
@@ -1,14 +1,48 @@
+use self::abort::{Abort, Aborts};
+use self::error::CategorizedError;
+use self::inspector::MessageInspector;
+use self::keyring::Keyring;
+#[cfg(feature = "keygen")]
+use self::router::ProtocolMessageNotification;
+use self::router::{
+    FromRoutedMessage, InspectedMessage, MessageRouter, PhaseMessage, WireMessage, PROTOCOL_VERSION,
+};
+#[cfg(feature = "sign")]
+use self::router::{OfflineProtocolMessageNotification, PartialSignatureNotification};
+use self::sequence::{in_sequence, Sequencer, MESSAGE_GAP_TIMEOUT};
+use self::subscription::Subscription;
+use self::timings::{PhaseTiming, Timings};
+#[cfg(feature = "sign")]
+use crate::utils::{
+    signature::{eip712_digest, normalize_s, to_der, HashAlgorithm},
+    signers::{self, normalize_signers, validate_signers},
+};
 use crate::{
-    client::json_rpc::JsonRpc,
-    utils::serializer::{
-        deserialize_any_from_js, serialize_any_to_js, serialize_response_to_js,
-        serialize_str_error_to_js,
+    client::{
+        json_rpc::{ConnectionEvent, JsonRpc, RequestHandler},
+        transport::{webrtc::DataChannelTransport, websocket::WebSocketTransport, Transport},
     },
+    utils::crypto,
+    utils::derivation,
+    utils::encryption,
+    utils::logger,
+    utils::secret::{self, SecretLocalKey},
+    utils::serializer::{deserialize_any_from_js, serialize_any_to_js},
+    utils::signature,
+    utils::timeout::enforce_timeout,
+};
+use anyhow::Result;
+use curv::{
+    arithmetic::Converter,
+    elliptic::curves::{Scalar, Secp256k1},
+    BigInt,
+};
+use futures::{
+    channel::{mpsc, oneshot},
+    future, pin_mut, select, FutureExt, SinkExt, Stream, StreamExt, TryStreamExt,
 };
-use anyhow::{Context, Result};
-use curv::{arithmetic::Converter, elliptic::curves::Secp256k1, BigInt};
-use futures::{future, pin_mut, SinkExt, Stream, StreamExt, TryStreamExt};
 use gloo_utils::format::JsValueSerdeExt;
+use json_rpc_types::{ErrorCode, Request};
 use mpc_manager::{
     service::{
         group_service::{GroupCreateRequest, GroupJoinRequest, GroupMethod},
@@ -20,31 +54,46 @@ use mpc_manager::{
     },
     state::{parameters::Parameters, session::SessionKind},
 };
-use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::{
-    keygen::{Keygen, LocalKey, ProtocolMessage},
-    sign::{OfflineProtocolMessage, OfflineStage, PartialSignature, SignManual},
+#[cfg(feature = "sign")]
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::party_i::SignatureRecid;
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::keygen::LocalKey;
+#[cfg(feature = "keygen")]
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::keygen::{
+    Keygen, ProtocolMessage,
 };
-use round_based::AsyncProtocol;
-use serde::Serialize;
-use std::{
-    collections::VecDeque,
-    str::FromStr,
-    sync::{Arc, Mutex},
-    time::Duration,
+#[cfg(feature = "sign")]
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::sign::{
+    OfflineProtocolMessage, OfflineStage, PartialSignature, SignManual,
 };
+use round_based::AsyncProtocol;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{future::Future, str::FromStr, sync::Arc, time::Duration};
 use thiserror::Error;
-use tokio::sync::broadcast;
 use tokio_stream::wrappers::BroadcastStream;
 use uuid::Uuid;
-use wasm_bindgen::{prelude::wasm_bindgen, JsError, JsValue};
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{RtcDataChannel, WebSocket};
+use zeroize::Zeroize;
 
+mod abort;
+mod error;
+mod inspector;
+mod key_share;
+mod keyring;
+mod options;
+mod persistence;
+mod router;
+mod sequence;
+mod session_map;
+mod sign_response;
+#[cfg(feature = "simulate")]
+mod simulate;
+mod subscription;
+mod timings;
 mod types;
 
-type ProtocolMessageNotification = SessionMessageNotification<round_based::Msg<ProtocolMessage>>;
-type OfflineProtocolMessageNotification =
-    SessionMessageNotification<round_based::Msg<OfflineProtocolMessage>>;
-type PartialSignatureNotification = SessionMessageNotification<round_based::Msg<PartialSignature>>;
-
 #[derive(Debug, Error)]
 pub enum MultiPartyEcdsaError {
     #[error("invalid group id `${0}`")]
@@ -54,99 +103,297 @@ pub enum MultiPartyEcdsaError {
     #[error("invalid local key")]
     InvalidLocalKey,
     #[error("protocol execution failed")]
-    FailedProtocolExecution(String), //FIXME: should implement with #[source]
+    FailedProtocolExecution(#[source] Box<dyn std::error::Error>),
+    #[error("protocol execution timed out")]
+    Timeout,
+    #[error("session was not ready before the deadline")]
+    SessionNotReady,
+    #[error("invalid tweak, must be lower than the curve order")]
+    InvalidTweak,
+    #[error("local key is corrupted or inconsistent")]
+    CorruptLocalKey,
+    #[error("generated key is inconsistent with the commitments of the group")]
+    InconsistentKey,
+    #[error("invalid parameters: {reason}")]
+    InvalidParameters { reason: String },
+    #[cfg(feature = "sign")]
+    #[error("invalid hex data: {0}")]
+    InvalidHex(String),
+    #[cfg(feature = "sign")]
+    #[error("invalid signer set: {reason}")]
+    InvalidSignerSet { reason: String },
+    #[cfg(feature = "sign")]
+    #[error("only {received} of {expected} partial signatures arrived before the deadline")]
+    IncompleteSignature { received: usize, expected: usize },
+    #[error("group is full")]
+    GroupFull,
+    #[error("already signed up to the session")]
+    AlreadySignedUp,
+    #[error("group not found")]
+    GroupNotFound,
+    #[error("session not found")]
+    SessionNotFound,
+    #[error("session was aborted by this party")]
+    Aborted,
+    #[error("party `{party}` aborted the session: {reason}")]
+    PeerAborted { party: u16, reason: String },
+    #[error("instance closed")]
+    Closed,
+}
+
+impl MultiPartyEcdsaError {
+    /// Recognizes the errors of the manager that callers may want to handle.
+    ///
+    /// The manager reports them with generic error codes, so they're told
+    /// apart by their message, only for the methods that can fail with them.
+    /// Ids that are well formed but unknown to the manager end up here, while
+    /// malformed ones are rejected before any request as `InvalidGroupId` or
+    /// `InvalidSessionId`.
+    fn from_rpc_error(
+        method: &str,
+        error: &json_rpc_types::Error<serde_json::Value>,
+    ) -> Option<Self> {
+        let message = error.message.as_str().to_lowercase();
+        let group_join = method == GroupMethod::GroupJoin.to_string();
+        let session_create = method == SessionMethod::SessionCreate.to_string();
+        let session_join = method == SessionMethod::SessionSignup.to_string()
+            || method == SessionMethod::SessionLogin.to_string();
+        let not_found = |kind: &str| message.contains(kind) && message.contains("not found");
+        if group_join && message.contains("full") {
+            Some(MultiPartyEcdsaError::GroupFull)
+        } else if session_join && message.contains("already") {
+            Some(MultiPartyEcdsaError::AlreadySignedUp)
+        } else if session_join && not_found("session") {
+            Some(MultiPartyEcdsaError::SessionNotFound)
+        } else if (group_join || session_create || session_join) && not_found("group") {
+            Some(MultiPartyEcdsaError::GroupNotFound)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct KeygenResponse {
+    #[serde(rename = "localKey")]
+    local_key: SecretLocalKey,
+    #[serde(rename = "publicKey")]
+    public_key: String,
+    #[serde(rename = "publicKeyCompressed")]
+    public_key_compressed: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timings: Option<Vec<PhaseTiming>>,
+}
+
+impl KeygenResponse {
+    fn new(local_key: SecretLocalKey) -> Self {
+        let public_key = local_key.public_key();
+        Self {
+            public_key: hex::encode(public_key.to_bytes(false).as_ref()),
+            public_key_compressed: hex::encode(public_key.to_bytes(true).as_ref()),
+            local_key,
+            timings: None,
+        }
+    }
+
+    #[cfg(feature = "keygen")]
+    fn with_timings(mut self, timings: Option<Vec<PhaseTiming>>) -> Self {
+        self.timings = timings;
+        self
+    }
+}
+
+#[cfg(feature = "sign")]
+#[derive(Serialize)]
+struct SignBitcoinResponse {
+    signature: String,
+    sighash: String,
+}
+
+#[cfg(feature = "sign")]
+#[derive(Serialize)]
+struct SignResponse {
+    #[serde(flatten)]
+    signature: SignatureRecid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timings: Option<Vec<PhaseTiming>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LocalKeyInfo {
+    party_index: u16,
+    parties: u16,
+    threshold: u16,
+    public_key: String,
+}
+
+#[derive(Serialize)]
+struct VersionInfo {
+    #[serde(rename = "crate")]
+    crate_version: &'static str,
+    protocol: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HealthResponse {
+    ok: bool,
+    latency_ms: f64,
 }
 
-#[derive(Default)]
-struct PendingMessages {
-    protocol_messages: Mutex<VecDeque<ProtocolMessageNotification>>,
-    offline_protocol_messages: Mutex<VecDeque<OfflineProtocolMessageNotification>>,
-    partial_signature_messages: Mutex<VecDeque<PartialSignatureNotification>>,
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Stats {
+    pending_requests: usize,
+    pending_protocol_messages: usize,
+    pending_offline_messages: usize,
+    pending_partial_messages: usize,
 }
 
-struct MessageChannels {
-    protocol_message_tx: broadcast::Sender<ProtocolMessageNotification>,
-    offline_protocol_message_tx: broadcast::Sender<OfflineProtocolMessageNotification>,
-    partial_signature_message_tx: broadcast::Sender<PartialSignatureNotification>,
+#[derive(Deserialize)]
+struct ManagerCall {
+    method: String,
+    #[serde(default)]
+    params: Option<serde_json::Value>,
 }
 
 #[wasm_bindgen]
 pub struct MultiPartyEcdsa {
     json_rpc: JsonRpc,
-    pending_messages: Arc<PendingMessages>,
-    message_channels: MessageChannels,
+    message_router: Arc<MessageRouter>,
+    protocol_timeout: Duration,
+    #[cfg(feature = "sign")]
+    partial_signature_timeout: Duration,
+    with_timings: bool,
+    compress_messages: bool,
+    encrypt_messages: bool,
+    #[cfg(feature = "keygen")]
+    validate_keygen: bool,
+    keyring: Arc<Keyring>,
+    aborts: Arc<Aborts>,
+    auth_token: Option<String>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
 }
 
 #[wasm_bindgen]
 impl MultiPartyEcdsa {
     #[wasm_bindgen(constructor)]
-    pub fn new(url: &str, timeout_in_ms: Option<u32>) -> Self {
-        let timeout = timeout_in_ms.map(|t| Duration::from_millis(t.into()));
-        let json_rpc = JsonRpc::new(url.into(), timeout).unwrap();
-        let pending_messages = Arc::new(PendingMessages::default());
-        let message_channels = MessageChannels {
-            protocol_message_tx: broadcast::channel::<ProtocolMessageNotification>(32).0,
-            offline_protocol_message_tx: broadcast::channel::<OfflineProtocolMessageNotification>(
-                32,
-            )
-            .0,
-            partial_signature_message_tx: broadcast::channel::<PartialSignatureNotification>(32).0,
+    pub fn new(
+        url: &str,
+        timeout_in_ms: Option<u32>,
+        options: Option<types::MultiPartyEcdsaOptions>,
+    ) -> Result<MultiPartyEcdsa, CategorizedError> {
+        let options: options::Options = match options {
+            Some(options) => deserialize_any_from_js(options.into())?,
+            None => options::Options::default(),
         };
+        let transport = WebSocketTransport::new(options.url(url), options.reconnect_policy())
+            .map_err(CategorizedError::invalid_input)?;
+        Ok(MultiPartyEcdsa::with_transport(
+            transport,
+            timeout_in_ms,
+            options,
+        ))
+    }
 
-        let mut incoming_messages = json_rpc.get_notification_receiver::<serde_json::Value>(
-            SessionMethod::SessionMessage.to_string(),
-        );
+    /// Creates a new `MultiPartyEcdsa` using an already constructed websocket,
+    /// for environments without a global `WebSocket` (e.g. Node.js).
+    #[wasm_bindgen(js_name = "fromWebSocket")]
+    pub fn from_websocket(
+        websocket: types::WebSocketLike,
+        timeout_in_ms: Option<u32>,
+        options: Option<types::MultiPartyEcdsaOptions>,
+    ) -> Result<MultiPartyEcdsa, CategorizedError> {
+        let options: options::Options = match options {
+            Some(options) => deserialize_any_from_js(options.into())?,
+            None => options::Options::default(),
+        };
+        let transport = WebSocketTransport::from_websocket(websocket.into())
+            .map_err(CategorizedError::invalid_input)?;
+        Ok(MultiPartyEcdsa::with_transport(
+            transport,
+            timeout_in_ms,
+            options,
+        ))
+    }
 
-        let pending_messages_c = pending_messages.clone();
-        let protocol_message_tx = message_channels.protocol_message_tx.clone();
-        let offline_protocol_message_tx = message_channels.offline_protocol_message_tx.clone();
-        let partial_signature_message_tx = message_channels.partial_signature_message_tx.clone();
+    /// Creates a new `MultiPartyEcdsa` exchanging messages through a WebRTC
+    /// data channel, with a peer coordinating the session as the manager
+    /// would. The reconnection options are ignored, as the channel is
+    /// negotiated by the caller.
+    #[wasm_bindgen(js_name = "fromDataChannel")]
+    pub fn from_data_channel(
+        channel: RtcDataChannel,
+        timeout_in_ms: Option<u32>,
+        options: Option<types::MultiPartyEcdsaOptions>,
+    ) -> Result<MultiPartyEcdsa, CategorizedError> {
+        let options: options::Options = match options {
+            Some(options) => deserialize_any_from_js(options.into())?,
+            None => options::Options::default(),
+        };
+        Ok(MultiPartyEcdsa::with_transport(
+            DataChannelTransport::new(channel),
+            timeout_in_ms,
+            options,
+        ))
+    }
 
-        wasm_bindgen_futures::spawn_local(async move {
-            while let Some(Ok(message)) = incoming_messages.next().await {
-                if let Ok(message) =
-                    serde_json::from_value::<ProtocolMessageNotification>(message.clone())
-                {
-                    if protocol_message_tx.send(message.clone()).is_err() {
-                        pending_messages_c
-                            .protocol_messages
-                            .lock()
-                            .unwrap()
-                            .push_back(message.clone())
-                    }
-                    continue;
-                }
-                if let Ok(message) =
-                    serde_json::from_value::<OfflineProtocolMessageNotification>(message.clone())
-                {
-                    if offline_protocol_message_tx.send(message.clone()).is_err() {
-                        pending_messages_c
-                            .offline_protocol_messages
-                            .lock()
-                            .unwrap()
-                            .push_back(message.clone())
-                    }
-                    continue;
-                }
-                if let Ok(message) = serde_json::from_value::<PartialSignatureNotification>(message)
-                {
-                    if partial_signature_message_tx.send(message.clone()).is_err() {
-                        pending_messages_c
-                            .partial_signature_messages
-                            .lock()
-                            .unwrap()
-                            .push_back(message)
-                    }
-                    continue;
-                }
-            }
-        });
+    #[wasm_bindgen(getter, js_name = "connectionState")]
+    pub fn connection_state(&self) -> types::ConnectionState {
+        let state = match self.json_rpc.ready_state() {
+            WebSocket::CONNECTING => "connecting",
+            WebSocket::OPEN => "open",
+            WebSocket::CLOSING => "closing",
+            _ => "closed",
+        };
+        JsValue::from_str(state).into()
+    }
 
-        Self {
-            json_rpc,
-            pending_messages,
-            message_channels,
+    /// Checks that the manager is reachable, measuring the round-trip time
+    /// of a `ping` request. Any response counts, so managers without a
+    /// `ping` method answer with a method not found error just as fast.
+    #[wasm_bindgen]
+    pub async fn health(
+        &self,
+        timeout_in_ms: Option<u32>,
+    ) -> Result<types::HealthResponse, CategorizedError> {
+        log::info!("Checking health of host");
+        let start = js_sys::Date::now();
+        let res = self
+            .json_rpc
+            .send_message_with_timeout::<serde_json::Value>(
+                "ping".into(),
+                None,
+                self.request_timeout(timeout_in_ms),
+            )
+            .await;
+        let latency_ms = js_sys::Date::now() - start;
+        if let Err(e) = &res {
+            log::warn!("Health check failed: {}", e);
         }
+
+        let health = HealthResponse {
+            ok: res.is_ok(),
+            latency_ms,
+        };
+        Ok(serialize_any_to_js(&health)?.into())
+    }
+
+    /// Returns the number of requests waiting for a response and of protocol
+    /// messages waiting for their protocol to start, for diagnostics.
+    #[wasm_bindgen]
+    pub fn stats(&self) -> Result<types::Stats, CategorizedError> {
+        let counts = self.message_router.pending_counts();
+        let stats = Stats {
+            pending_requests: self.json_rpc.pending_requests(),
+            pending_protocol_messages: counts.protocol,
+            pending_offline_messages: counts.offline,
+            pending_partial_messages: counts.partial,
+        };
+        Ok(serialize_any_to_js(&stats)?.into())
     }
 
     #[wasm_bindgen(js_name = "groupCreate")]
@@ -154,38 +401,43 @@ impl MultiPartyEcdsa {
         &mut self,
         parties: u16,
         threshold: u16,
-    ) -> Result<types::GroupCreateResponse, JsError> {
+        timeout_in_ms: Option<u32>,
+    ) -> Result<types::GroupCreateResponse, CategorizedError> {
         log::info!("Creating group");
+        let method = GroupMethod::GroupCreate.to_string();
         let res = self
             .json_rpc
-            .send_message(
-                GroupMethod::GroupCreate.to_string(),
+            .send_message_with_timeout(
+                method.clone(),
                 Some(GroupCreateRequest {
                     parameters: Parameters::new(parties, threshold)
-                        .map_err(serialize_str_error_to_js)?,
+                        .map_err(CategorizedError::invalid_input)?,
                 }),
+                self.request_timeout(timeout_in_ms),
             )
-            .await
-            .map_err(serialize_str_error_to_js)?;
-        serialize_response_to_js(res).map(|val| val.into())
+            .await?;
+        response_to_js(&method, res).map(|val| val.into())
     }
 
     #[wasm_bindgen(js_name = "groupJoin")]
     pub async fn group_join(
         &mut self,
         group_id: &str,
-    ) -> Result<types::GroupJoinResponse, JsError> {
+        timeout_in_ms: Option<u32>,
+    ) -> Result<types::GroupJoinResponse, CategorizedError> {
         log::info!("Joining group with group_id {}", group_id);
-        let group_id = Uuid::try_from(group_id).map_err(serialize_str_error_to_js)?;
+        let group_id = Uuid::try_from(group_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidGroupId(group_id.into()))?;
+        let method = GroupMethod::GroupJoin.to_string();
         let res = self
             .json_rpc
-            .send_message(
-                GroupMethod::GroupJoin.to_string(),
+            .send_message_with_timeout(
+                method.clone(),
                 Some(GroupJoinRequest { group_id }),
+                self.request_timeout(timeout_in_ms),
             )
-            .await
-            .map_err(serialize_str_error_to_js)?;
-        serialize_response_to_js(res).map(|val| val.into())
+            .await?;
+        response_to_js(&method, res).map(|val| val.into())
     }
 
     #[wasm_bindgen(js_name = "sessionCreate")]
@@ -194,25 +446,28 @@ impl MultiPartyEcdsa {
         group_id: &str,
         kind: types::SessionKind,
         value: JsValue,
-    ) -> Result<types::SessionCreateResponse, JsError> {
+        timeout_in_ms: Option<u32>,
+    ) -> Result<types::SessionCreateResponse, CategorizedError> {
         log::info!("Creating session with group_id {}", group_id);
-        let group_id = Uuid::try_from(group_id).map_err(serialize_str_error_to_js)?;
-        let kind =
-            SessionKind::from_str(&kind.as_string().unwrap()).map_err(serialize_str_error_to_js)?;
-        let value = value.into_serde().map_err(serialize_str_error_to_js)?;
+        let group_id = Uuid::try_from(group_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidGroupId(group_id.into()))?;
+        let kind = SessionKind::from_str(&kind.as_string().unwrap_or_default())
+            .map_err(CategorizedError::invalid_input)?;
+        let value = value.into_serde()?;
+        let method = SessionMethod::SessionCreate.to_string();
         let res = self
             .json_rpc
-            .send_message(
-                SessionMethod::SessionCreate.to_string(),
+            .send_message_with_timeout(
+                method.clone(),
                 Some(SessionCreateRequest {
                     group_id,
                     kind,
                     value,
                 }),
+                self.request_timeout(timeout_in_ms),
             )
-            .await
-            .map_err(serialize_str_error_to_js)?;
-        serialize_response_to_js(res).map(|val| val.into())
+            .await?;
+        response_to_js(&method, res).map(|val| val.into())
     }
 
     #[wasm_bindgen(js_name = "sessionSignup")]
@@ -220,101 +475,419 @@ impl MultiPartyEcdsa {
         &mut self,
         group_id: &str,
         session_id: &str,
-    ) -> Result<types::SessionSignupResponse, JsError> {
+        timeout_in_ms: Option<u32>,
+    ) -> Result<types::SessionSignupResponse, CategorizedError> {
         log::info!(
             "Signing up to session with group_id {} and session_id {}",
             group_id,
             session_id
         );
-        let group_id = Uuid::try_from(group_id).map_err(serialize_str_error_to_js)?;
-        let session_id = Uuid::try_from(session_id).map_err(serialize_str_error_to_js)?;
+        let group_id = Uuid::try_from(group_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidGroupId(group_id.into()))?;
+        let session_id = Uuid::try_from(session_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidSessionId(session_id.into()))?;
+        let method = SessionMethod::SessionSignup.to_string();
         let res = self
             .json_rpc
-            .send_message(
-                SessionMethod::SessionSignup.to_string(),
+            .send_message_with_timeout(
+                method.clone(),
                 Some(SessionSignupRequest {
                     group_id,
                     session_id,
                 }),
+                self.request_timeout(timeout_in_ms),
             )
-            .await
-            .map_err(serialize_str_error_to_js)?;
-        serialize_response_to_js(res).map(|val| val.into())
+            .await?;
+        response_to_js(&method, res).map(|val| val.into())
     }
 
     #[wasm_bindgen(js_name = "sessionLogin")]
     pub async fn session_login(
-        &mut self,
+        &self,
         group_id: &str,
         session_id: &str,
         party_number: u16,
-    ) -> Result<types::SessionSignupResponse, JsError> {
+        timeout_in_ms: Option<u32>,
+    ) -> Result<types::SessionSignupResponse, CategorizedError> {
         log::info!(
             "Logging to session with group_id {}, session_id {} and party number {}",
             group_id,
             session_id,
             party_number
         );
-        let group_id = Uuid::try_from(group_id).map_err(serialize_str_error_to_js)?;
-        let session_id = Uuid::try_from(session_id).map_err(serialize_str_error_to_js)?;
+        let group_id = Uuid::try_from(group_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidGroupId(group_id.into()))?;
+        let session_id = Uuid::try_from(session_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidSessionId(session_id.into()))?;
+        let method = SessionMethod::SessionLogin.to_string();
         let res = self
             .json_rpc
-            .send_message(
-                SessionMethod::SessionLogin.to_string(),
+            .send_message_with_timeout(
+                method.clone(),
                 Some(SessionLoginRequest {
                     group_id,
                     session_id,
                     party_number,
                 }),
+                self.request_timeout(timeout_in_ms),
             )
+            .await?;
+        response_to_js(&method, res).map(|val| val.into())
+    }
+
+    /// Rejoins a session after the connection dropped, so a running `keygen`
+    /// or `sign` keeps receiving its messages: reconnects unless the
+    /// connection is open and logs in again as the given party, as the
+    /// manager only routes messages to connections logged in to a session.
+    ///
+    /// Messages sent by the other parties while disconnected are lost, so
+    /// the protocol only completes if none was addressed to this party.
+    #[wasm_bindgen(js_name = "resumeSession")]
+    pub async fn resume_session(
+        &self,
+        group_id: &str,
+        session_id: &str,
+        party_number: u16,
+        timeout_in_ms: Option<u32>,
+    ) -> Result<types::SessionSignupResponse, CategorizedError> {
+        log::info!(
+            "Resuming session with group_id {}, session_id {} and party number {}",
+            group_id,
+            session_id,
+            party_number
+        );
+        self.ensure_open()?;
+        if self.json_rpc.ready_state() != WebSocket::OPEN {
+            self.json_rpc.reconnect().await?;
+        }
+        self.session_login(group_id, session_id, party_number, timeout_in_ms)
             .await
-            .map_err(serialize_str_error_to_js)?;
-        serialize_response_to_js(res).map(|val| val.into())
     }
 
-    #[wasm_bindgen(js_name = "onSessionCreated")]
-    pub fn on_session_created(&self, callback: js_sys::Function) {
-        let mut incoming = self
-            .json_rpc
-            .get_notification_receiver::<SessionCreatedNotification>(
-                SessionEvent::SessionCreated.to_string(),
-            );
+    /// Releases the messages of a session, ignoring the ones received from
+    /// now on, along with the encryption keys and abort received for it.
+    /// `keygen` and `sign` close their session when done, so it's only
+    /// needed for sessions whose protocol is never started.
+    #[wasm_bindgen(js_name = "closeSession")]
+    pub fn close_session(&self, group_id: &str, session_id: &str) -> Result<(), CategorizedError> {
+        log::info!(
+            "Closing session with group_id {} and session_id {}",
+            group_id,
+            session_id
+        );
+        let group_id = Uuid::try_from(group_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidGroupId(group_id.into()))?;
+        let session_id = Uuid::try_from(session_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidSessionId(session_id.into()))?;
+        self.message_router.close_session(group_id, session_id);
+        self.keyring.close_session(group_id, session_id);
+        self.aborts.close_session(group_id, session_id);
+        Ok(())
+    }
 
-        wasm_bindgen_futures::spawn_local(async move {
-            while let Some(msg) = incoming.next().await {
-                let Ok(msg) = msg else { continue };
-                let Ok(msg) = serialize_any_to_js(msg) else { continue };
-                callback.call1(&JsValue::NULL, &msg).unwrap(); //FIXME
+    /// Aborts the `keygen` or `sign` running on a session, which fails with
+    /// an `Aborted` error, and tells the other parties so theirs fail right
+    /// away with a `PeerAborted` error instead of timing out. A protocol
+    /// started on the session afterwards fails too.
+    #[wasm_bindgen(js_name = "signAbort")]
+    pub async fn sign_abort(
+        &self,
+        group_id: &str,
+        session_id: &str,
+        reason: Option<String>,
+    ) -> Result<(), CategorizedError> {
+        log::info!(
+            "Aborting session with group_id {} and session_id {}",
+            group_id,
+            session_id
+        );
+        let group_id = Uuid::try_from(group_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidGroupId(group_id.into()))?;
+        let session_id = Uuid::try_from(session_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidSessionId(session_id.into()))?;
+        self.ensure_open()?;
+        self.aborts.abort(group_id, session_id, Abort::Local);
+        let request = JsonRpc::new_request(
+            None,
+            SessionMethod::SessionMessage.to_string(),
+            Some(SessionMessageRequest {
+                group_id,
+                session_id,
+                receiver: None,
+                message: WireMessage::Abort {
+                    abort: reason.unwrap_or_else(|| "aborted by the user".into()),
+                },
+            }),
+        );
+        self.json_rpc
+            .get_notification_sender()
+            .send(request)
+            .await?;
+        Ok(())
+    }
+
+    /// Fails every request waiting for a response with a `Cancelled` error.
+    /// If `stop_routing` is set, the routing task is stopped too, so
+    /// protocol messages are no longer delivered to running sessions.
+    #[wasm_bindgen(js_name = "cancelAll")]
+    pub fn cancel_all(&mut self, stop_routing: Option<bool>) {
+        log::info!("Cancelling all pending requests");
+        self.json_rpc.cancel_all();
+        if stop_routing.unwrap_or(false) {
+            if let Some(shutdown_tx) = self.shutdown_tx.take() {
+                log::info!("Stopping routing task");
+                let _ = shutdown_tx.send(()); // Ignores already stopped task
             }
-        })
+        }
+    }
+
+    /// Releases the instance: closes the connection to the manager for good,
+    /// stops routing protocol messages and drops the pending ones. Requests
+    /// waiting for a response fail, and so does every method called from now
+    /// on, with an "instance closed" error.
+    #[wasm_bindgen]
+    pub fn close(&mut self) {
+        log::info!("Closing instance");
+        self.json_rpc.close();
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(()); // Ignores already stopped task
+        }
+        self.message_router.clear_pending();
+    }
+
+    /// Closes the connection to the manager and opens a new one, resolving
+    /// once it's open. Registered callbacks are kept, while requests waiting
+    /// for a response fail.
+    #[wasm_bindgen]
+    pub async fn reconnect(&self) -> Result<(), CategorizedError> {
+        log::info!("Reconnecting to host");
+        Ok(self.json_rpc.reconnect().await?)
+    }
+
+    /// Connects to the manager at another url, resolving once the new
+    /// connection is open. The auth token of the options is kept, as are
+    /// the registered callbacks and subscriptions, while requests waiting
+    /// for a response fail.
+    #[wasm_bindgen(js_name = "setUrl")]
+    pub async fn set_url(&self, url: &str) -> Result<(), CategorizedError> {
+        log::info!("Switching host to {}", url);
+        let url = options::url_with_token(url, self.auth_token.as_deref());
+        Ok(self.json_rpc.set_url(url).await?)
+    }
+
+    /// Calls an arbitrary method of the manager, returning its raw result.
+    #[wasm_bindgen]
+    pub async fn call(
+        &self,
+        method: String,
+        params: JsValue,
+        timeout_in_ms: Option<u32>,
+    ) -> Result<JsValue, CategorizedError> {
+        log::info!("Calling method {}", method);
+        let params: Option<serde_json::Value> = deserialize_any_from_js(params)?;
+        let res = self
+            .json_rpc
+            .send_message_with_timeout(method.clone(), params, self.request_timeout(timeout_in_ms))
+            .await?;
+        response_to_js(&method, res)
+    }
+
+    /// Calls several methods of the manager in a single message, returning
+    /// their raw results in the same order. Fails with the error of the
+    /// first call that failed.
+    #[wasm_bindgen(js_name = "callBatch")]
+    pub async fn call_batch(
+        &self,
+        calls: types::ManagerCalls,
+        timeout_in_ms: Option<u32>,
+    ) -> Result<js_sys::Array, CategorizedError> {
+        let calls: Vec<ManagerCall> = deserialize_any_from_js(calls.into())?;
+        log::info!("Calling {} methods in a batch", calls.len());
+        let methods: Vec<String> = calls.iter().map(|call| call.method.clone()).collect();
+        let requests = calls
+            .into_iter()
+            .map(|call| (call.method, call.params))
+            .collect();
+        let responses = self
+            .json_rpc
+            .send_batch(requests, self.request_timeout(timeout_in_ms))
+            .await?;
+        let results = js_sys::Array::new();
+        for (method, res) in methods.iter().zip(responses) {
+            results.push(&response_to_js(method, res)?);
+        }
+        Ok(results)
     }
 
+    /// Answers the requests of a given method sent by the manager with the
+    /// value returned by the callback, which can be a promise. Errors thrown
+    /// by the callback are sent back as internal errors.
+    #[wasm_bindgen(js_name = "onRequest")]
+    pub fn on_request(&self, method: String, callback: js_sys::Function) {
+        let handler: RequestHandler = Box::new(move |params| {
+            let callback = callback.clone();
+            Box::pin(async move {
+                let internal_error = |e: JsValue| {
+                    let message = e
+                        .dyn_ref::<js_sys::Error>()
+                        .map(|e| String::from(e.message()))
+                        .or_else(|| e.as_string())
+                        .unwrap_or("unknown error".into());
+                    json_rpc_types::Error::with_custom_msg(ErrorCode::InternalError, &message)
+                };
+                let params = serialize_any_to_js(params).map_err(|e| internal_error(e.into()))?;
+                let result = callback
+                    .call1(&JsValue::NULL, &params)
+                    .map_err(internal_error)?;
+                let result = JsFuture::from(js_sys::Promise::resolve(&result))
+                    .await
+                    .map_err(internal_error)?;
+                deserialize_any_from_js(result).map_err(|e| internal_error(e.into()))
+            })
+        });
+        self.json_rpc.set_request_handler(method, handler);
+    }
+
+    /// Calls the callback with every session created, until unsubscribed.
+    #[wasm_bindgen(js_name = "onSessionCreated")]
+    pub fn on_session_created(&self, callback: js_sys::Function) -> Subscription {
+        self.on_session_event::<SessionCreatedNotification>(
+            SessionEvent::SessionCreated,
+            callback,
+            false,
+        )
+    }
+
+    /// Calls the callback with the next session created only.
+    #[wasm_bindgen(js_name = "onceSessionCreated")]
+    pub fn once_session_created(&self, callback: js_sys::Function) -> Subscription {
+        self.on_session_event::<SessionCreatedNotification>(
+            SessionEvent::SessionCreated,
+            callback,
+            true,
+        )
+    }
+
+    /// Calls the callback with every session ready, until unsubscribed.
     #[wasm_bindgen(js_name = "onSessionReady")]
-    pub fn on_session_ready(&self, callback: js_sys::Function) {
-        let mut incoming = self
+    pub fn on_session_ready(&self, callback: js_sys::Function) -> Subscription {
+        self.on_session_event::<SessionReadyNotification>(
+            SessionEvent::SessionReady,
+            callback,
+            false,
+        )
+    }
+
+    /// Calls the callback with the next session ready only.
+    #[wasm_bindgen(js_name = "onceSessionReady")]
+    pub fn once_session_ready(&self, callback: js_sys::Function) -> Subscription {
+        self.on_session_event::<SessionReadyNotification>(
+            SessionEvent::SessionReady,
+            callback,
+            true,
+        )
+    }
+
+    /// Calls the callback with the params of every notification of the given
+    /// method sent by the manager, as is, until unsubscribed. Useful for
+    /// events the library doesn't wrap yet.
+    #[wasm_bindgen]
+    pub fn subscribe(&self, method: String, callback: js_sys::Function) -> Subscription {
+        let incoming = self
+            .json_rpc
+            .get_notification_receiver::<serde_json::Value>(method);
+        subscription::listen(incoming, callback, false)
+    }
+
+    /// Returns an async iterator over copies of the protocol messages
+    /// received from now on, for debugging and auditing. Observing them
+    /// doesn't interfere with the running protocols.
+    #[wasm_bindgen(js_name = "inspectMessages")]
+    pub fn inspect_messages(&self) -> types::InspectedMessages {
+        let incoming = self.message_router.tap().filter_map(|message| {
+            future::ready(match message {
+                Ok(message) => InspectedMessage::try_from(&message)
+                    .map_err(|e| log::warn!("Failed to serialize inspected message: {}", e))
+                    .ok(),
+                Err(e) => {
+                    log::warn!("Message inspector lagged: {}", e);
+                    None
+                }
+            })
+        });
+        MessageInspector::into_async_iterator(incoming)
+    }
+
+    /// Calls the callback with the `{ message }` of every connection error,
+    /// including messages that couldn't be sent.
+    #[wasm_bindgen(js_name = "onError")]
+    pub fn on_error(&self, callback: js_sys::Function) {
+        self.on_connection_event(callback, |event| {
+            matches!(event, ConnectionEvent::Error { .. })
+        });
+    }
+
+    /// Calls the callback with the `{ code, reason }` of every disconnection.
+    #[wasm_bindgen(js_name = "onDisconnect")]
+    pub fn on_disconnect(&self, callback: js_sys::Function) {
+        self.on_connection_event(callback, |event| {
+            matches!(event, ConnectionEvent::Closed { .. })
+        });
+    }
+
+    /// Waits until the given session is ready, failing if it isn't ready
+    /// before the timeout (defaults to the protocol timeout).
+    ///
+    /// Must be called before the session gets ready, as past notifications
+    /// are not kept.
+    #[wasm_bindgen(js_name = "waitForSessionReady")]
+    pub async fn wait_for_session_ready(
+        &self,
+        group_id: &str,
+        session_id: &str,
+        timeout_in_ms: Option<u32>,
+    ) -> Result<types::SessionReadyNotification, CategorizedError> {
+        log::info!(
+            "Waiting for session with group_id {} and session_id {} to be ready",
+            group_id,
+            session_id
+        );
+        let group_id = Uuid::try_from(group_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidGroupId(group_id.into()))?;
+        let session_id = Uuid::try_from(session_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidSessionId(session_id.into()))?;
+        self.ensure_open()?;
+        let deadline = timeout_in_ms
+            .map(|t| Duration::from_millis(t.into()))
+            .unwrap_or(self.protocol_timeout);
+
+        let incoming = self
             .json_rpc
             .get_notification_receiver::<SessionReadyNotification>(
                 SessionEvent::SessionReady.to_string(),
-            );
+            )
+            .try_filter(|msg| {
+                future::ready(msg.group_id == group_id && msg.session_id == session_id)
+            });
+        pin_mut!(incoming);
 
-        wasm_bindgen_futures::spawn_local(async move {
-            while let Some(msg) = incoming.next().await {
-                let Ok(msg) = msg else { continue };
-                let Ok(msg) = serialize_any_to_js(msg) else { continue };
-                callback.call1(&JsValue::NULL, &msg).unwrap(); //FIXME
-            }
-        })
+        let msg = enforce_timeout(deadline, incoming.try_next())
+            .await
+            .map_err(|_| MultiPartyEcdsaError::SessionNotReady)??
+            .ok_or(MultiPartyEcdsaError::SessionNotReady)?;
+        Ok(serialize_any_to_js(msg)?.into())
     }
 
+    #[cfg(feature = "keygen")]
     #[wasm_bindgen]
     pub async fn keygen(
-        &mut self,
+        &self,
         group_id: &str,
         session_id: &str,
         party_number: u16,
         parties: u16,
         threshold: u16,
-    ) -> Result<types::KeygenResponse, JsError> {
+    ) -> Result<types::KeygenResponse, CategorizedError> {
         log::info!(
             "Generating new key with group_id {}, session_id {} and party number {}",
             group_id,
@@ -325,77 +898,556 @@ impl MultiPartyEcdsa {
             .map_err(|_| MultiPartyEcdsaError::InvalidGroupId(group_id.into()))?;
         let session_id = Uuid::try_from(session_id)
             .map_err(|_| MultiPartyEcdsaError::InvalidSessionId(session_id.into()))?;
+        validate_keygen_parameters(party_number, parties, threshold)?;
+        self.ensure_open()?;
+        let keygen = Keygen::new(party_number, threshold, parties)
+            .map_err(CategorizedError::invalid_input)?;
+        // Release the messages, keys and abort of the session once done,
+        // whatever the outcome
+        let _session_guard = self.message_router.session_guard(group_id, session_id);
+        let _keyring_guard = self.keyring.session_guard(group_id, session_id);
+        let _abort_guard = self.aborts.session_guard(group_id, session_id);
 
         // Create channels for communication with async-protocol
-        let incoming = self
-            .get_protocol_message_receiver()
-            .filter_map(|message| match message {
-                Ok(message) => {
-                    if !(message.group_id == group_id
-                        && message.session_id == session_id
-                        && message.sender != party_number)
-                    {
-                        return future::ready(None);
-                    }
-                    future::ready(Some(Ok::<_, anyhow::Error>(message.message)))
-                }
-                Err(err) => future::ready(Some(Err(err))),
-            });
+        let incoming = in_sequence(
+            self.get_message_receiver::<ProtocolMessageNotification>(group_id, session_id)
+                .try_filter(|message| future::ready(message.sender != party_number)),
+            MESSAGE_GAP_TIMEOUT,
+        )
+        .map_ok(|message| message.message);
+        let peers: Vec<u16> = (1..=parties).filter(|i| *i != party_number).collect();
+        let keyring = self
+            .exchange_keys(group_id, session_id, party_number, &peers)
+            .await?;
+        let compress = self.compress_messages;
+        let mut sequencer = Sequencer::default();
         let outgoing = self
             .json_rpc
             .get_notification_sender()
-            .with::<_, _, _, anyhow::Error>(|message: round_based::Msg<ProtocolMessage>| {
-                let params = SessionMessageRequest {
+            .with::<_, _, _, anyhow::Error>(move |message: round_based::Msg<ProtocolMessage>| {
+                session_message_request(
                     group_id,
                     session_id,
-                    message: message.clone(),
-                    receiver: message.receiver,
-                };
-                future::ready(Ok(JsonRpc::new_request(
-                    None,
-                    SessionMethod::SessionMessage.to_string(),
-                    Some(params),
-                )))
+                    message.receiver,
+                    PhaseMessage::Keygen(sequencer.sequence(message)),
+                    compress,
+                    keyring.clone(),
+                )
             });
 
-        let keygen =
-            Keygen::new(party_number, threshold, parties).map_err(serialize_str_error_to_js)?;
-
         let incoming = incoming.fuse();
         pin_mut!(incoming);
         pin_mut!(outgoing);
 
-        let local_key = AsyncProtocol::new(keygen, incoming, outgoing)
-            .run()
-            .await
-            .map_err(serialize_str_error_to_js)?;
-
-        #[derive(Serialize)]
-        struct KeygenResponse {
-            #[serde(rename = "localKey")]
-            local_key: LocalKey<Secp256k1>,
-            #[serde(rename = "publicKey")]
-            public_key: String,
+        let mut timings = Timings::new(self.with_timings);
+        let timer = timings.start();
+        let mut protocol = AsyncProtocol::new(timer.timed(keygen), incoming, outgoing);
+        let keygen = enforce_timeout(self.protocol_timeout, protocol.run());
+        let local_key = self
+            .abortable(group_id, session_id, keygen)
+            .await?
+            .map_err(|_| MultiPartyEcdsaError::Timeout)?
+            .map_err(|e| MultiPartyEcdsaError::FailedProtocolExecution(Box::new(e)))?;
+        let local_key = SecretLocalKey::new(local_key);
+        timings.finish("keygen", timer);
+        if self.validate_keygen {
+            validate_generated_key(&local_key)?;
         }
 
-        let public_key = hex::encode(local_key.public_key().to_bytes(false).as_ref());
-        let output = KeygenResponse {
+        let response = KeygenResponse::new(local_key).with_timings(timings.into_phases());
+        Ok(serialize_any_to_js(response)?.into())
+    }
+
+    #[cfg(feature = "sign")]
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn sign(
+        &self,
+        group_id: &str,
+        session_id: &str,
+        local_key: JsValue,
+        parties: Vec<u16>,
+        data_to_sign: &[u8],
+        low_s: Option<bool>,
+        hash_algorithm: Option<types::HashAlgorithm>,
+    ) -> Result<types::SignResponse, CategorizedError> {
+        let hash_algorithm = hash_algorithm
+            .map(|algorithm| HashAlgorithm::from_str(&algorithm.as_string().unwrap_or_default()))
+            .transpose()?
+            .unwrap_or_default();
+        let data_to_sign = hash_algorithm.hash(data_to_sign)?;
+        let response = self
+            .sign_data(
+                group_id,
+                session_id,
+                local_key,
+                parties,
+                &data_to_sign,
+                low_s.unwrap_or(false),
+            )
+            .await?;
+        Ok(serialize_any_to_js(&response)?.into())
+    }
+
+    /// Signs hex encoded data, with or without a `0x` prefix, like `sign`.
+    #[cfg(feature = "sign")]
+    #[wasm_bindgen(js_name = "signHex")]
+    pub async fn sign_hex(
+        &self,
+        group_id: &str,
+        session_id: &str,
+        local_key: JsValue,
+        parties: Vec<u16>,
+        data_hex: &str,
+        low_s: Option<bool>,
+    ) -> Result<types::SignResponse, CategorizedError> {
+        let data_hex = data_hex.strip_prefix("0x").unwrap_or(data_hex);
+        let data_to_sign =
+            hex::decode(data_hex).map_err(|e| MultiPartyEcdsaError::InvalidHex(e.to_string()))?;
+        self.sign(
+            group_id,
+            session_id,
             local_key,
-            public_key,
+            parties,
+            &data_to_sign,
+            low_s,
+            None,
+        )
+        .await
+    }
+
+    /// Signs EIP-712 typed data given its domain separator and struct hash,
+    /// both 32 bytes long. The signature is normalized to its low-S form,
+    /// as Ethereum requires.
+    #[cfg(feature = "sign")]
+    #[wasm_bindgen(js_name = "signTypedData")]
+    pub async fn sign_typed_data(
+        &self,
+        group_id: &str,
+        session_id: &str,
+        local_key: JsValue,
+        parties: Vec<u16>,
+        domain_separator: &[u8],
+        struct_hash: &[u8],
+    ) -> Result<types::SignResponse, CategorizedError> {
+        let invalid = |name: &str, value: &[u8]| MultiPartyEcdsaError::InvalidParameters {
+            reason: format!("{} must be 32 bytes, got {}", name, value.len()),
+        };
+        let domain_separator = domain_separator
+            .try_into()
+            .map_err(|_| invalid("domain separator", domain_separator))?;
+        let struct_hash = struct_hash
+            .try_into()
+            .map_err(|_| invalid("struct hash", struct_hash))?;
+        let digest = eip712_digest(domain_separator, struct_hash);
+        self.sign(
+            group_id,
+            session_id,
+            local_key,
+            parties,
+            &digest,
+            Some(true),
+            None,
+        )
+        .await
+    }
+
+    /// Signs a Bitcoin transaction given its sighash preimage, resolving to
+    /// the low-S DER signature followed by the sighash type byte, hex
+    /// encoded, along with the signed sighash (the double SHA-256 of the
+    /// preimage).
+    #[cfg(feature = "sign")]
+    #[wasm_bindgen(js_name = "signBitcoin")]
+    pub async fn sign_bitcoin(
+        &self,
+        group_id: &str,
+        session_id: &str,
+        local_key: JsValue,
+        parties: Vec<u16>,
+        preimage: &[u8],
+        sighash_type: u8,
+    ) -> Result<types::SignBitcoinResponse, CategorizedError> {
+        let sighash = Sha256::digest(Sha256::digest(preimage));
+        let response = self
+            .sign_data(group_id, session_id, local_key, parties, &sighash, true)
+            .await?;
+
+        let mut signature = to_der(
+            &response.signature.r.to_bigint(),
+            &response.signature.s.to_bigint(),
+        );
+        signature.push(sighash_type);
+        let response = SignBitcoinResponse {
+            signature: hex::encode(signature),
+            sighash: hex::encode(sighash),
         };
+        Ok(serialize_any_to_js(response)?.into())
+    }
+
+    /// Signs several messages, each in its own session, running their
+    /// protocols concurrently. Resolves to one result per message, in
+    /// order, holding either its `signature` or the `error` it failed with.
+    #[cfg(feature = "sign")]
+    #[wasm_bindgen(js_name = "signMultiple")]
+    pub async fn sign_multiple(
+        &self,
+        group_id: &str,
+        session_ids: Vec<JsValue>,
+        local_key: JsValue,
+        parties: Vec<u16>,
+        messages: Vec<JsValue>,
+        low_s: Option<bool>,
+    ) -> Result<types::SignMultipleResults, CategorizedError> {
+        log::info!(
+            "Signing {} messages with group_id {} and parties {:?}",
+            messages.len(),
+            group_id,
+            parties
+        );
+        let group_id = Uuid::try_from(group_id)
+            .map_err(|_| MultiPartyEcdsaError::InvalidGroupId(group_id.into()))?;
+        if session_ids.len() != messages.len() {
+            return Err(MultiPartyEcdsaError::InvalidParameters {
+                reason: format!(
+                    "expected one session per message, got {} sessions for {} messages",
+                    session_ids.len(),
+                    messages.len()
+                ),
+            }
+            .into());
+        }
+        let session_ids = session_ids
+            .iter()
+            .map(|session_id| {
+                let session_id = session_id.as_string().unwrap_or_default();
+                Uuid::try_from(session_id.as_str())
+                    .map_err(|_| MultiPartyEcdsaError::InvalidSessionId(session_id))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let messages = messages
+            .into_iter()
+            .map(|message| {
+                message
+                    .dyn_into::<js_sys::Uint8Array>()
+                    .map(|message| message.to_vec())
+                    .map_err(|_| MultiPartyEcdsaError::InvalidParameters {
+                        reason: "messages must be Uint8Arrays".into(),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let local_key = parse_local_key(local_key)?;
+        let parties = parse_signers(&parties, &local_key)?;
 
-        serialize_any_to_js(&output).map(|val| val.into())
+        let results = future::join_all(session_ids.into_iter().zip(messages.iter()).map(
+            |(session_id, message)| {
+                self.sign_in_session(
+                    group_id,
+                    session_id,
+                    &local_key,
+                    parties.clone(),
+                    message,
+                    low_s.unwrap_or(false),
+                )
+            },
+        ))
+        .await;
+
+        let array = js_sys::Array::new();
+        for result in results {
+            let (field, value) = match result {
+                Ok(response) => ("signature", serialize_any_to_js(&response)?),
+                Err(e) => ("error", e.into()),
+            };
+            let entry = js_sys::Object::new();
+            js_sys::Reflect::set(&entry, &field.into(), &value)
+                .map_err(|_| CategorizedError::invalid_input("cannot build sign result"))?;
+            array.push(&entry);
+        }
+        Ok(JsValue::from(array).into())
     }
 
-    #[wasm_bindgen]
-    pub async fn sign(
-        &mut self,
+    fn on_session_event<T>(
+        &self,
+        event: SessionEvent,
+        callback: js_sys::Function,
+        once: bool,
+    ) -> Subscription
+    where
+        T: DeserializeOwned + Serialize + 'static,
+    {
+        let incoming = self
+            .json_rpc
+            .get_notification_receiver::<T>(event.to_string());
+        subscription::listen(incoming, callback, once)
+    }
+
+    fn on_connection_event(
+        &self,
+        callback: js_sys::Function,
+        filter: impl Fn(&ConnectionEvent) -> bool + 'static,
+    ) {
+        let mut incoming = BroadcastStream::new(self.json_rpc.get_connection_event_receiver());
+
+        wasm_bindgen_futures::spawn_local(async move {
+            while let Some(event) = incoming.next().await {
+                let Ok(event) = event else { continue };
+                if !filter(&event) {
+                    continue;
+                }
+                let Ok(event) = serialize_any_to_js(event) else { continue };
+                if let Err(e) = callback.call1(&JsValue::NULL, &event) {
+                    log::error!("Connection event callback failed: {:?}", e);
+                }
+            }
+        })
+    }
+
+    fn ensure_open(&self) -> Result<(), MultiPartyEcdsaError> {
+        if self.json_rpc.is_closed() {
+            return Err(MultiPartyEcdsaError::Closed);
+        }
+        Ok(())
+    }
+
+    /// Returns the timeout of a request, falling back to the one given to
+    /// the constructor.
+    fn request_timeout(&self, timeout_in_ms: Option<u32>) -> Duration {
+        timeout_in_ms
+            .map(|t| Duration::from_millis(t.into()))
+            .unwrap_or_else(|| self.json_rpc.timeout())
+    }
+
+    fn get_message_receiver<T>(
+        &self,
+        group_id: Uuid,
+        session_id: Uuid,
+    ) -> impl Stream<Item = Result<T>>
+    where
+        T: FromRoutedMessage + 'static,
+    {
+        self.message_router.subscribe::<T>(group_id, session_id)
+    }
+}
+
+impl MultiPartyEcdsa {
+    /// Creates a new `MultiPartyEcdsa` connected to the host through the
+    /// given transport.
+    pub fn with_transport(
+        transport: impl Transport + 'static,
+        timeout_in_ms: Option<u32>,
+        options: options::Options,
+    ) -> Self {
+        let timeout = timeout_in_ms.map(|t| Duration::from_millis(t.into()));
+        let mut json_rpc = JsonRpc::new(
+            transport,
+            timeout,
+            options.channel_capacity(),
+            options.max_message_size(),
+        );
+        json_rpc.set_retry_policy(options.retry_policy());
+        if let Some(rate_limit) = options.rate_limit() {
+            json_rpc.set_rate_limit(rate_limit);
+        }
+        if let Some(interval) = options.keepalive_interval() {
+            json_rpc.start_keepalive(interval);
+        }
+        // Store pending messages while they wait for their receivers, if enabled
+        let message_router = match options.pending_messages_persistence() {
+            Some(max_age) => {
+                let (persistence_tx, persistence_rx) = mpsc::unbounded();
+                let message_router = Arc::new(MessageRouter::new(
+                    options.channel_capacity(),
+                    options.max_pending_messages(),
+                    Some(persistence_tx),
+                ));
+                wasm_bindgen_futures::spawn_local(persistence::persistence_task(
+                    Arc::downgrade(&message_router),
+                    persistence_rx,
+                    max_age,
+                ));
+                message_router
+            }
+            None => Arc::new(MessageRouter::new(
+                options.channel_capacity(),
+                options.max_pending_messages(),
+                None,
+            )),
+        };
+
+        let mut incoming_messages = json_rpc
+            .get_notification_receiver::<SessionMessageNotification<WireMessage>>(
+                SessionMethod::SessionMessage.to_string(),
+            )
+            .fuse();
+
+        // Route messages until the instance is dropped
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let message_router_c = message_router.clone();
+        let keyring = Arc::new(Keyring::default());
+        let keyring_c = keyring.clone();
+        let aborts = Arc::new(Aborts::default());
+        let aborts_c = aborts.clone();
+        // Compressed messages are bounded once decompressed too
+        let max_message_size = options.max_message_size();
+        wasm_bindgen_futures::spawn_local(async move {
+            let mut shutdown_rx = shutdown_rx.fuse();
+            loop {
+                let message = select! {
+                    message = incoming_messages.next() => message,
+                    _ = shutdown_rx => break,
+                };
+                let Some(message) = message else { break };
+                let message = match message {
+                    Ok(message) => message,
+                    Err(e) => {
+                        log::warn!("Failed to receive session message: {}", e);
+                        continue;
+                    }
+                };
+                // Decoded in place, so messages keep their order
+                let SessionMessageNotification {
+                    group_id,
+                    session_id,
+                    sender,
+                    message,
+                } = message;
+                let message = match message {
+                    WireMessage::Handshake { handshake } => {
+                        if let Err(e) =
+                            keyring_c.add_public_key(group_id, session_id, sender, &handshake)
+                        {
+                            log::warn!("Failed to handle handshake: {}", e);
+                        }
+                        continue;
+                    }
+                    WireMessage::Abort { abort: reason } => {
+                        log::warn!("Party {} aborted the session: {}", sender, reason);
+                        let abort = Abort::Peer {
+                            party: sender,
+                            reason,
+                        };
+                        aborts_c.abort(group_id, session_id, abort);
+                        continue;
+                    }
+                    WireMessage::Encrypted { encrypted } => {
+                        match keyring_c
+                            .decrypt(group_id, session_id, sender, &encrypted)
+                            .map_err(anyhow::Error::from)
+                            .and_then(|json| Ok(serde_json::from_slice(&json)?))
+                        {
+                            Ok(message) => message,
+                            Err(e) => {
+                                log::warn!("Failed to decrypt session message: {}", e);
+                                continue;
+                            }
+                        }
+                    }
+                    message => message,
+                };
+                let message = match message.decode(max_message_size).await {
+                    Ok(message) => message,
+                    Err(e) => {
+                        log::warn!("Failed to decode session message: {}", e);
+                        continue;
+                    }
+                };
+                let message = SessionMessageNotification {
+                    group_id,
+                    session_id,
+                    sender,
+                    message,
+                };
+                message_router_c.route(message.into());
+            }
+        });
+
+        Self {
+            json_rpc,
+            message_router,
+            protocol_timeout: options.protocol_timeout(),
+            #[cfg(feature = "sign")]
+            partial_signature_timeout: options.partial_signature_timeout(),
+            with_timings: options.with_timings.unwrap_or(false),
+            compress_messages: options.compress_messages.unwrap_or(false),
+            encrypt_messages: options.encrypt_messages.unwrap_or(false),
+            #[cfg(feature = "keygen")]
+            validate_keygen: options.validate_keygen.unwrap_or(false),
+            keyring,
+            aborts,
+            auth_token: options.auth_token.clone(),
+            shutdown_tx: Some(shutdown_tx),
+        }
+    }
+
+    /// Sends the ephemeral public key of this party to the others of the
+    /// session and waits for theirs, if messages are encrypted. Returns the
+    /// keyring to encrypt the messages of the session with.
+    async fn exchange_keys(
+        &self,
+        group_id: Uuid,
+        session_id: Uuid,
+        party_number: u16,
+        peers: &[u16],
+    ) -> Result<Option<Arc<Keyring>>, CategorizedError> {
+        if !self.encrypt_messages {
+            return Ok(None);
+        }
+        let handshake = self.keyring.join(group_id, session_id, party_number);
+        let request = JsonRpc::new_request(
+            None,
+            SessionMethod::SessionMessage.to_string(),
+            Some(SessionMessageRequest {
+                group_id,
+                session_id,
+                receiver: None,
+                message: WireMessage::Handshake { handshake },
+            }),
+        );
+        self.json_rpc
+            .get_notification_sender()
+            .send(request)
+            .await?;
+        let exchanged = enforce_timeout(
+            self.protocol_timeout,
+            self.keyring.wait_for(group_id, session_id, peers),
+        );
+        self.abortable(group_id, session_id, exchanged)
+            .await?
+            .map_err(|_| MultiPartyEcdsaError::Timeout)?;
+        Ok(Some(self.keyring.clone()))
+    }
+
+    /// Runs a step of the protocol of a session until it's done or the
+    /// session is aborted, by this party or another.
+    async fn abortable<F: Future>(
+        &self,
+        group_id: Uuid,
+        session_id: Uuid,
+        step: F,
+    ) -> Result<F::Output, MultiPartyEcdsaError> {
+        let step = step.fuse();
+        let aborted = self.aborts.wait(group_id, session_id).fuse();
+        pin_mut!(step, aborted);
+        select! {
+            output = step => Ok(output),
+            abort = aborted => Err(match abort {
+                Abort::Local => MultiPartyEcdsaError::Aborted,
+                Abort::Peer { party, reason } => MultiPartyEcdsaError::PeerAborted { party, reason },
+            }),
+        }
+    }
+
+    /// Signs data after parsing and validating the parameters of `sign`.
+    #[cfg(feature = "sign")]
+    async fn sign_data(
+        &self,
         group_id: &str,
         session_id: &str,
         local_key: JsValue,
         parties: Vec<u16>,
         data_to_sign: &[u8],
-    ) -> Result<types::SignResponse, JsError> {
+        low_s: bool,
+    ) -> Result<SignResponse, CategorizedError> {
         log::info!(
             "Signing data with group_id {}, session_id {} and parties {:?}",
             group_id,
@@ -406,181 +1458,722 @@ impl MultiPartyEcdsa {
             .map_err(|_| MultiPartyEcdsaError::InvalidGroupId(group_id.into()))?;
         let session_id = Uuid::try_from(session_id)
             .map_err(|_| MultiPartyEcdsaError::InvalidSessionId(session_id.into()))?;
-        let local_key: LocalKey<Secp256k1> = deserialize_any_from_js(local_key)
-            .map_err(|_| MultiPartyEcdsaError::InvalidLocalKey)?;
+        let local_key = parse_local_key(local_key)?;
+        let parties = parse_signers(&parties, &local_key)?;
+
+        self.sign_in_session(
+            group_id,
+            session_id,
+            &local_key,
+            parties,
+            data_to_sign,
+            low_s,
+        )
+        .await
+    }
+
+    /// Runs the signing protocol in a session, once its parameters are
+    /// validated. The key is borrowed, so the sessions of `signMultiple`
+    /// don't each hold a copy of it.
+    #[cfg(feature = "sign")]
+    async fn sign_in_session(
+        &self,
+        group_id: Uuid,
+        session_id: Uuid,
+        local_key: &LocalKey<Secp256k1>,
+        parties: Vec<u16>,
+        data_to_sign: &[u8],
+        low_s: bool,
+    ) -> Result<SignResponse, CategorizedError> {
+        self.ensure_open()?;
+        // Release the messages, keys and abort of the session once done,
+        // whatever the outcome
+        let _session_guard = self.message_router.session_guard(group_id, session_id);
+        let _keyring_guard = self.keyring.session_guard(group_id, session_id);
+        let _abort_guard = self.aborts.session_guard(group_id, session_id);
 
         let party_number = local_key.i;
         let number_of_parties = parties.len();
+        let peers: Vec<u16> = parties
+            .iter()
+            .copied()
+            .filter(|i| *i != party_number)
+            .collect();
+        let session_keyring = self
+            .exchange_keys(group_id, session_id, party_number, &peers)
+            .await?;
 
         // Create channels for offline stage communication with async-protocol
-        let incoming = self
-            .get_offline_protocol_message_receiver()
-            .try_filter(|message| {
-                future::ready(
-                    message.group_id == group_id
-                        && message.session_id == session_id
-                        && message.sender != party_number,
-                )
-            })
-            .map_ok(|message| message.message);
+        let incoming = in_sequence(
+            self.get_message_receiver::<OfflineProtocolMessageNotification>(group_id, session_id)
+                .try_filter(|message| future::ready(message.sender != party_number)),
+            MESSAGE_GAP_TIMEOUT,
+        )
+        .map_ok(|message| message.message);
+        let keyring = session_keyring.clone();
+        let compress = self.compress_messages;
+        let mut sequencer = Sequencer::default();
         let outgoing = self
             .json_rpc
             .get_notification_sender()
-            .with::<_, _, _, anyhow::Error>(|message: round_based::Msg<OfflineProtocolMessage>| {
-                let params = SessionMessageRequest {
-                    group_id,
-                    session_id,
-                    message: message.clone(),
-                    receiver: message.receiver,
-                };
-                future::ready(Ok(JsonRpc::new_request(
-                    None,
-                    SessionMethod::SessionMessage.to_string(),
-                    Some(params),
-                )))
-            });
+            .with::<_, _, _, anyhow::Error>(
+                move |message: round_based::Msg<OfflineProtocolMessage>| {
+                    session_message_request(
+                        group_id,
+                        session_id,
+                        message.receiver,
+                        PhaseMessage::Offline(sequencer.sequence(message)),
+                        compress,
+                        keyring.clone(),
+                    )
+                },
+            );
 
         let incoming = incoming.fuse();
         pin_mut!(incoming);
         pin_mut!(outgoing);
 
-        let signing = OfflineStage::new(party_number, parties, local_key)?;
-        let completed_offline_stage = AsyncProtocol::new(signing, incoming, outgoing)
-            .run()
-            .await
-            .map_err(|e| MultiPartyEcdsaError::FailedProtocolExecution(e.to_string()))?;
+        let mut timings = Timings::new(self.with_timings);
+        let timer = timings.start();
+        // The offline stage takes its own copy of the key, which it doesn't
+        // wipe and keeps out of reach until it's dropped
+        let signing = OfflineStage::new(party_number, parties, local_key.clone())
+            .map_err(|e| MultiPartyEcdsaError::FailedProtocolExecution(Box::new(e)))?;
+        let mut protocol = AsyncProtocol::new(timer.timed(signing), incoming, outgoing);
+        let offline_stage = enforce_timeout(self.protocol_timeout, protocol.run());
+        let completed_offline_stage = self
+            .abortable(group_id, session_id, offline_stage)
+            .await?
+            .map_err(|_| MultiPartyEcdsaError::Timeout)?
+            .map_err(|e| MultiPartyEcdsaError::FailedProtocolExecution(Box::new(e)))?;
+        timings.finish("offline", timer);
 
         // Create channels for online stage communication with async-protocol
-        let incoming = self
-            .get_partial_signature_message_receiver()
-            .try_filter(|message| {
-                future::ready(
-                    message.group_id == group_id
-                        && message.session_id == session_id
-                        && message.sender != party_number,
-                )
-            })
-            .map_ok(|message| message.message);
+        let incoming = in_sequence(
+            self.get_message_receiver::<PartialSignatureNotification>(group_id, session_id)
+                .try_filter(|message| future::ready(message.sender != party_number)),
+            MESSAGE_GAP_TIMEOUT,
+        )
+        .map_ok(|message| message.message);
+        let keyring = session_keyring;
+        let compress = self.compress_messages;
+        let mut sequencer = Sequencer::default();
         let outgoing = self
             .json_rpc
             .get_notification_sender()
-            .with::<_, _, _, anyhow::Error>(|message: round_based::Msg<PartialSignature>| {
-                let params = SessionMessageRequest {
+            .with::<_, _, _, anyhow::Error>(move |message: round_based::Msg<PartialSignature>| {
+                session_message_request(
                     group_id,
                     session_id,
-                    message: message.clone(),
-                    receiver: message.receiver,
-                };
-                future::ready(Ok(JsonRpc::new_request(
-                    None,
-                    SessionMethod::SessionMessage.to_string(),
-                    Some(params),
-                )))
+                    message.receiver,
+                    PhaseMessage::Partial(sequencer.sequence(message)),
+                    compress,
+                    keyring.clone(),
+                )
             });
 
         let incoming = incoming.fuse();
         pin_mut!(incoming);
         pin_mut!(outgoing);
 
+        let timer = timings.start();
         let (signing, partial_signature) =
-            SignManual::new(BigInt::from_bytes(data_to_sign), completed_offline_stage)?;
+            SignManual::new(BigInt::from_bytes(data_to_sign), completed_offline_stage)
+                .map_err(|e| MultiPartyEcdsaError::FailedProtocolExecution(Box::new(e)))?;
 
+        // Subscribed before sending, so no disconnection is missed
+        let disconnections = BroadcastStream::new(self.json_rpc.get_connection_event_receiver())
+            .filter(|event| future::ready(matches!(event, Ok(ConnectionEvent::Closed { .. }))));
         outgoing
             .send(round_based::Msg {
                 sender: party_number,
                 receiver: None,
                 body: partial_signature,
             })
-            .await
-            .map_err(serialize_str_error_to_js)?;
+            .await?;
 
-        let partial_signatures: Vec<_> = incoming
-            .take(number_of_parties - 1)
-            .map_ok(|msg| msg.body)
-            .try_collect()
-            .await
-            .map_err(serialize_str_error_to_js)?;
+        // Collect partial signatures with its own deadline, as a signer leaving
+        // after the offline stage would make this wait forever. A disconnection
+        // ends it right away, as the partial signatures sent meanwhile are lost
+        let expected = number_of_parties - 1;
+        let mut partial_signatures = Vec::with_capacity(expected);
+        let collected = {
+            let collect = async {
+                let mut incoming = incoming.take(expected);
+                while let Some(msg) = incoming.try_next().await? {
+                    partial_signatures.push(msg.body);
+                }
+                Ok::<_, anyhow::Error>(())
+            }
+            .fuse();
+            let disconnected = disconnections.into_future().fuse();
+            pin_mut!(collect, disconnected);
+            let collected = enforce_timeout(self.partial_signature_timeout, async {
+                select! {
+                    result = collect => result,
+                    _ = disconnected => {
+                        log::warn!("Disconnected while collecting partial signatures");
+                        Ok(())
+                    }
+                }
+            });
+            self.abortable(group_id, session_id, collected).await?
+        };
+        if let Ok(result) = collected {
+            result?;
+        }
+        if partial_signatures.len() < expected {
+            return Err(MultiPartyEcdsaError::IncompleteSignature {
+                received: partial_signatures.len(),
+                expected,
+            }
+            .into());
+        }
         let signature = signing
             .complete(&partial_signatures)
-            .context("online stage failed")
-            .map_err(serialize_str_error_to_js)?;
+            .map_err(|e| MultiPartyEcdsaError::FailedProtocolExecution(Box::new(e)))?;
+        let signature = if low_s {
+            normalize_s(signature)
+        } else {
+            signature
+        };
+        timings.finish("online", timer);
 
-        serialize_any_to_js(&signature).map(|val| val.into())
+        Ok(SignResponse {
+            signature,
+            timings: timings.into_phases(),
+        })
     }
+}
 
-    fn get_protocol_message_receiver(
-        &self,
-    ) -> impl Stream<Item = Result<ProtocolMessageNotification>> {
-        // Create receiver stream
-        let receiver = BroadcastStream::new(self.message_channels.protocol_message_tx.subscribe())
-            .map_err(|e| e.into());
-
-        // Resend all pending messages
-        let mut pending = self.pending_messages.protocol_messages.lock().unwrap();
-        while pending.len() > 0 {
-            let message = pending.pop_front().unwrap();
-            self.message_channels
-                .protocol_message_tx
-                .send(message)
-                .unwrap();
+impl Drop for MultiPartyEcdsa {
+    /// Stops the routing task. The connection is closed when the `JsonRpc`
+    /// is dropped right after.
+    fn drop(&mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(()); // Ignores already stopped task
         }
+    }
+}
 
-        receiver
+/// Builds the request sending a protocol message to the other parties of a
+/// session, compressing it if enabled.
+async fn session_message_request(
+    group_id: Uuid,
+    session_id: Uuid,
+    receiver: Option<u16>,
+    message: PhaseMessage,
+    compress: bool,
+    keyring: Option<Arc<Keyring>>,
+) -> Result<Request<SessionMessageRequest<WireMessage>>> {
+    let mut message = WireMessage::encode(message, compress).await?;
+    if let Some(keyring) = keyring {
+        let json = serde_json::to_vec(&message)?;
+        let encrypted = keyring.encrypt(group_id, session_id, receiver, &json)?;
+        message = WireMessage::Encrypted { encrypted };
     }
+    let params = SessionMessageRequest {
+        group_id,
+        session_id,
+        receiver,
+        message,
+    };
+    Ok(JsonRpc::new_request(
+        None,
+        SessionMethod::SessionMessage.to_string(),
+        Some(params),
+    ))
+}
 
-    fn get_offline_protocol_message_receiver(
-        &self,
-    ) -> impl Stream<Item = Result<OfflineProtocolMessageNotification>> {
-        // Create receiver stream
-        let receiver = BroadcastStream::new(
-            self.message_channels
-                .offline_protocol_message_tx
-                .subscribe(),
+/// Checks a key produced by keygen against the commitments of the group,
+/// catching a peer that passed the protocol but led to a wrong key.
+#[cfg(feature = "keygen")]
+fn validate_generated_key(local_key: &LocalKey<Secp256k1>) -> Result<(), MultiPartyEcdsaError> {
+    if secret::is_consistent(local_key) && secret::matches_commitments(local_key) {
+        Ok(())
+    } else {
+        Err(MultiPartyEcdsaError::InconsistentKey)
+    }
+}
+
+/// Deserializes a local key, checking its secret share is consistent with
+/// its public data. The key is wiped once dropped.
+fn parse_local_key(value: JsValue) -> Result<SecretLocalKey, MultiPartyEcdsaError> {
+    let local_key: SecretLocalKey =
+        secret::deserialize_secret_from_js(&value).ok_or(MultiPartyEcdsaError::InvalidLocalKey)?;
+    if !secret::is_consistent(&local_key) {
+        return Err(MultiPartyEcdsaError::CorruptLocalKey);
+    }
+    Ok(local_key)
+}
+
+/// Sorts and dedupes the signers, checking they are in range, enough to
+/// sign and include the party of the local key.
+#[cfg(feature = "sign")]
+fn parse_signers(
+    parties: &[u16],
+    local_key: &LocalKey<Secp256k1>,
+) -> Result<Vec<u16>, MultiPartyEcdsaError> {
+    let parties = normalize_signers(parties);
+    validate_signers(&parties, local_key.i, local_key.t, local_key.n).map_err(|e| {
+        MultiPartyEcdsaError::InvalidSignerSet {
+            reason: e.to_string(),
+        }
+    })?;
+    Ok(parties)
+}
+
+#[cfg(feature = "keygen")]
+/// Checks the keygen parameters the same way the manager does for groups,
+/// and that the party number belongs to them.
+fn validate_keygen_parameters(
+    party_number: u16,
+    parties: u16,
+    threshold: u16,
+) -> Result<(), MultiPartyEcdsaError> {
+    let invalid = |reason: String| MultiPartyEcdsaError::InvalidParameters { reason };
+    Parameters::new(parties, threshold).map_err(|e| invalid(e.to_string()))?;
+    if threshold == 0 || threshold >= parties {
+        return Err(invalid(format!(
+            "threshold must be between 1 and {}, got {}",
+            parties.saturating_sub(1),
+            threshold
+        )));
+    }
+    if party_number == 0 || party_number > parties {
+        return Err(invalid(format!(
+            "party number must be between 1 and {}, got {}",
+            parties, party_number
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the version of the library and of its protocol messages, along
+/// with the commit it was built from if known.
+#[wasm_bindgen]
+pub fn version() -> Result<types::VersionInfo, CategorizedError> {
+    let version = VersionInfo {
+        crate_version: env!("CARGO_PKG_VERSION"),
+        protocol: PROTOCOL_VERSION,
+        commit: option_env!("GIT_COMMIT"),
+    };
+    Ok(serialize_any_to_js(version)?.into())
+}
+
+/// Recovers the public key (uncompressed, hex encoded) that produced a
+/// signature of the given data.
+#[wasm_bindgen(js_name = "recoverPublicKey")]
+pub fn recover_public_key(
+    data: &[u8],
+    r: &str,
+    s: &str,
+    recid: u8,
+) -> Result<String, CategorizedError> {
+    let r = BigInt::from_hex(r).map_err(|_| signature::SignatureError::InvalidSignature)?;
+    let s = BigInt::from_hex(s).map_err(|_| signature::SignatureError::InvalidSignature)?;
+    let public_key = signature::recover_public_key(&BigInt::from_bytes(data), &r, &s, recid)?;
+    Ok(hex::encode(public_key.to_bytes(false).as_ref()))
+}
+
+/// Adds two points (hex encoded, compressed or not), returning the sum
+/// uncompressed and hex encoded, as computed by the protocol.
+#[wasm_bindgen(js_name = "pointAdd")]
+pub fn point_add(a: &str, b: &str) -> Result<String, CategorizedError> {
+    let sum = crypto::point_add(&crypto::parse_point(a)?, &crypto::parse_point(b)?)?;
+    Ok(hex::encode(sum.to_bytes(false).as_ref()))
+}
+
+/// Multiplies the generator by a hex encoded scalar, returning the point
+/// uncompressed and hex encoded, e.g. to get the public key of a tweak.
+#[wasm_bindgen(js_name = "scalarMulBase")]
+pub fn scalar_mul_base(scalar: &str) -> Result<String, CategorizedError> {
+    let point = crypto::scalar_mul_base(&crypto::parse_scalar(scalar)?);
+    Ok(hex::encode(point.to_bytes(false).as_ref()))
+}
+
+/// Returns the EIP-55 checksummed Ethereum address of a hex encoded public
+/// key, compressed or not.
+#[wasm_bindgen(js_name = "pointToAddress")]
+pub fn point_to_address(point: &str) -> Result<String, CategorizedError> {
+    Ok(crypto::to_ethereum_address(&crypto::parse_point(point)?))
+}
+
+/// Derives a child key from a local key by applying an additive tweak, so
+/// its public key is `publicKey + tweak * G`. Every signer must derive its
+/// key with the same tweak.
+#[wasm_bindgen(js_name = "deriveChild")]
+pub fn derive_child(
+    local_key: JsValue,
+    tweak: &[u8],
+) -> Result<types::KeygenResponse, CategorizedError> {
+    let mut local_key = parse_local_key(local_key)?;
+    let tweak = BigInt::from_bytes(tweak);
+    if &tweak >= Scalar::<Secp256k1>::group_order() {
+        return Err(MultiPartyEcdsaError::InvalidTweak.into());
+    }
+    derivation::derive_child(&mut local_key, &Scalar::from_bigint(&tweak));
+    Ok(serialize_any_to_js(KeygenResponse::new(local_key))?.into())
+}
+
+/// Selects the minimal set of signers for the given local key among the
+/// available parties, always including the party of the key. The parsed
+/// key is wiped on every exit.
+#[cfg(feature = "sign")]
+#[wasm_bindgen(js_name = "selectSigners")]
+pub fn select_signers(
+    local_key: JsValue,
+    available: Vec<u16>,
+) -> Result<Vec<u16>, CategorizedError> {
+    let local_key = parse_local_key(local_key)?;
+    Ok(signers::select_signers(
+        &available,
+        local_key.i,
+        local_key.t,
+        local_key.n,
+    )?)
+}
+
+/// Returns the parameters and public key of a local key, so stored keys can
+/// be inspected without connecting to the manager. Only public data is
+/// read, and the parsed key is wiped before returning.
+#[wasm_bindgen(js_name = "localKeyInfo")]
+pub fn local_key_info(local_key: JsValue) -> Result<types::LocalKeyInfo, CategorizedError> {
+    let local_key = parse_local_key(local_key)?;
+    let info = LocalKeyInfo {
+        party_index: local_key.i,
+        parties: local_key.n,
+        threshold: local_key.t,
+        public_key: hex::encode(local_key.public_key().to_bytes(false).as_ref()),
+    };
+    Ok(serialize_any_to_js(info)?.into())
+}
+
+/// Encrypts a local key with a password, so it can be stored safely. Returns
+/// a base64 envelope to be decrypted with `importEncryptedKey`.
+#[wasm_bindgen(js_name = "exportEncryptedKey")]
+pub fn export_encrypted_key(
+    local_key: JsValue,
+    password: &str,
+) -> Result<String, CategorizedError> {
+    let local_key = parse_local_key(local_key)?;
+    let mut plaintext = serde_json::to_vec(&local_key)?;
+    let envelope = encryption::encrypt(&plaintext, password);
+    plaintext.zeroize();
+    Ok(envelope?)
+}
+
+/// Decrypts a local key encrypted with `exportEncryptedKey`.
+#[wasm_bindgen(js_name = "importEncryptedKey")]
+pub fn import_encrypted_key(envelope: &str, password: &str) -> Result<JsValue, CategorizedError> {
+    let mut plaintext = encryption::decrypt(envelope, password)?;
+    let local_key = serde_json::from_slice::<SecretLocalKey>(&plaintext);
+    plaintext.zeroize();
+    let local_key = local_key.map_err(|_| MultiPartyEcdsaError::InvalidLocalKey)?;
+    imported_key_to_js(local_key)
+}
+
+/// Serializes a local key to CBOR, a binary format more compact than the
+/// JSON representation returned by `keygen`, followed by its SHA-256
+/// checksum.
+#[wasm_bindgen(js_name = "exportKeyCbor")]
+pub fn export_key_cbor(local_key: JsValue) -> Result<Vec<u8>, CategorizedError> {
+    let local_key = parse_local_key(local_key)?;
+    let mut bytes = Vec::new();
+    ciborium::ser::into_writer(&local_key, &mut bytes).map_err(CategorizedError::invalid_input)?;
+    let checksum = Sha256::digest(&bytes);
+    bytes.extend_from_slice(&checksum);
+    Ok(bytes)
+}
+
+/// Deserializes a local key serialized with `exportKeyCbor`, verifying its
+/// checksum.
+#[wasm_bindgen(js_name = "importKeyCbor")]
+pub fn import_key_cbor(bytes: &[u8]) -> Result<JsValue, CategorizedError> {
+    let checksum_start = bytes
+        .len()
+        .checked_sub(Sha256::output_size())
+        .ok_or(MultiPartyEcdsaError::CorruptLocalKey)?;
+    let (bytes, checksum) = bytes.split_at(checksum_start);
+    if Sha256::digest(bytes).as_slice() != checksum {
+        return Err(MultiPartyEcdsaError::CorruptLocalKey.into());
+    }
+    let local_key: SecretLocalKey =
+        ciborium::de::from_reader(bytes).map_err(|_| MultiPartyEcdsaError::InvalidLocalKey)?;
+    imported_key_to_js(local_key)
+}
+
+/// Serializes the response of a manager request of the given method,
+/// categorizing its error as a manager one.
+fn response_to_js(
+    method: &str,
+    res: json_rpc_types::Response<serde_json::Value, serde_json::Value>,
+) -> Result<JsValue, CategorizedError> {
+    match res.payload {
+        Ok(result) => Ok(serialize_any_to_js(result)?),
+        Err(error) => Err(CategorizedError::from_rpc_error(method, error)),
+    }
+}
+
+/// Checks the consistency of an imported key and serializes it to JS.
+fn imported_key_to_js(local_key: SecretLocalKey) -> Result<JsValue, CategorizedError> {
+    if !secret::is_consistent(&local_key) {
+        return Err(MultiPartyEcdsaError::CorruptLocalKey.into());
+    }
+    Ok(serialize_any_to_js(&local_key)?)
+}
+
+/// Sends the log records of this thread to the callback as
+/// `{ level, target, message }` objects instead of the console, logging
+/// up to `level` if given. Passing `null` restores the console.
+#[wasm_bindgen(js_name = "setLogHandler")]
+pub fn set_log_handler(
+    callback: Option<js_sys::Function>,
+    level: Option<types::LogLevel>,
+) -> Result<(), CategorizedError> {
+    if let Some(level) = level {
+        set_log_level(level)?;
+    }
+    logger::set_handler(callback);
+    Ok(())
+}
+
+/// Sets the maximum level of the logged records.
+#[wasm_bindgen(js_name = "setLogLevel")]
+pub fn set_log_level(level: types::LogLevel) -> Result<(), CategorizedError> {
+    let level = logger::parse_level(&level.as_string().unwrap_or_default())?;
+    logger::set_level(level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::transport::memory::MemoryHub;
+    use serde_json::Value;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn rpc_error(method: impl ToString, message: &str) -> Option<MultiPartyEcdsaError> {
+        let error = json_rpc_types::Error::with_custom_msg(ErrorCode::InvalidParams, message);
+        MultiPartyEcdsaError::from_rpc_error(&method.to_string(), &error)
+    }
+
+    #[test]
+    fn recognizes_group_full_only_when_joining_a_group() {
+        assert!(matches!(
+            rpc_error(GroupMethod::GroupJoin, "Group is full"),
+            Some(MultiPartyEcdsaError::GroupFull)
+        ));
+        assert!(rpc_error(SessionMethod::SessionSignup, "session is full").is_none());
+        assert!(rpc_error(GroupMethod::GroupCreate, "group is full").is_none());
+    }
+
+    #[test]
+    fn recognizes_already_signed_up_only_when_joining_a_session() {
+        for method in [SessionMethod::SessionSignup, SessionMethod::SessionLogin] {
+            assert!(matches!(
+                rpc_error(method, "party already signed up to the session"),
+                Some(MultiPartyEcdsaError::AlreadySignedUp)
+            ));
+        }
+        assert!(rpc_error(GroupMethod::GroupJoin, "already a member of the group").is_none());
+        assert!(rpc_error(SessionMethod::SessionCreate, "session already exists").is_none());
+    }
+
+    #[test]
+    fn recognizes_session_not_found_only_for_requests_on_a_session() {
+        for method in [SessionMethod::SessionSignup, SessionMethod::SessionLogin] {
+            assert!(matches!(
+                rpc_error(method, "session not found"),
+                Some(MultiPartyEcdsaError::SessionNotFound)
+            ));
+        }
+        assert!(rpc_error(GroupMethod::GroupJoin, "session not found").is_none());
+    }
+
+    #[test]
+    fn recognizes_group_not_found_only_for_requests_on_a_group() {
+        let methods = [
+            GroupMethod::GroupJoin.to_string(),
+            SessionMethod::SessionCreate.to_string(),
+            SessionMethod::SessionSignup.to_string(),
+            SessionMethod::SessionLogin.to_string(),
+        ];
+        for method in methods {
+            assert!(matches!(
+                rpc_error(method, "Group not found"),
+                Some(MultiPartyEcdsaError::GroupNotFound)
+            ));
+        }
+        assert!(rpc_error(GroupMethod::GroupCreate, "group not found").is_none());
+        assert!(rpc_error("ping", "group not found").is_none());
+    }
+
+    fn field(value: impl Into<JsValue>, pointer: &str) -> Value {
+        let value: Value = deserialize_any_from_js(value.into()).unwrap();
+        value.pointer(pointer).cloned().unwrap()
+    }
+
+    fn string(value: impl Into<JsValue>, pointer: &str) -> String {
+        field(value, pointer).as_str().unwrap().to_owned()
+    }
+
+    /// Returns the name, category and message of the error of a request.
+    fn thrown<T>(res: Result<T, CategorizedError>) -> (String, String, String) {
+        let Err(error) = res else { panic!("request didn't fail") };
+        let error: js_sys::Error = JsValue::from(error).unchecked_into();
+        let category = js_sys::Reflect::get(&error, &"category".into()).unwrap();
+        (
+            error.name().into(),
+            category.as_string().unwrap(),
+            error.message().into(),
         )
-        .map_err(|e| e.into());
+    }
+
+    #[wasm_bindgen_test]
+    async fn tells_malformed_ids_from_unknown_ones() {
+        let hub = MemoryHub::new();
+        let mut client =
+            MultiPartyEcdsa::with_transport(hub.connect(), None, options::Options::default());
+        let unknown_id = Uuid::new_v4().to_string();
+
+        let res = client.group_join("not-a-group", None).await;
+        let message = MultiPartyEcdsaError::InvalidGroupId("not-a-group".into()).to_string();
+        assert_eq!(
+            thrown(res),
+            ("Error".into(), "invalidInput".into(), message)
+        );
+        let (name, category, _) = thrown(client.group_join(&unknown_id, None).await);
+        assert_eq!(
+            (name.as_str(), category.as_str()),
+            ("GroupNotFound", "manager")
+        );
+
+        let group = client.group_create(2, 1, None).await.unwrap();
+        let group_id = string(group, "/group/id");
+        let res = client
+            .session_signup(&group_id, "not-a-session", None)
+            .await;
+        let message = MultiPartyEcdsaError::InvalidSessionId("not-a-session".into()).to_string();
+        assert_eq!(
+            thrown(res),
+            ("Error".into(), "invalidInput".into(), message)
+        );
+        let res = client.session_signup(&group_id, &unknown_id, None).await;
+        let (name, category, _) = thrown(res);
+        assert_eq!(
+            (name.as_str(), category.as_str()),
+            ("SessionNotFound", "manager")
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn rejects_session_kinds_that_are_not_strings() {
+        let hub = MemoryHub::new();
+        let mut client =
+            MultiPartyEcdsa::with_transport(hub.connect(), None, options::Options::default());
+        let group = client.group_create(2, 1, None).await.unwrap();
+        let group_id = string(group, "/group/id");
+
+        let kind = JsValue::from(1).unchecked_into();
+        let res = client
+            .session_create(&group_id, kind, JsValue::NULL, None)
+            .await;
+        let (_, category, _) = thrown(res);
+        assert_eq!(category, "invalidInput");
+    }
+
+    #[cfg(all(feature = "keygen", feature = "sign"))]
+    #[wasm_bindgen_test]
+    async fn generates_a_key_and_signs_through_the_hub() {
+        let hub = MemoryHub::new();
+        let mut clients: Vec<MultiPartyEcdsa> = (0..3)
+            .map(|_| {
+                MultiPartyEcdsa::with_transport(hub.connect(), None, options::Options::default())
+            })
+            .collect();
 
-        // Resend all pending messages
-        let mut pending = self
-            .pending_messages
-            .offline_protocol_messages
-            .lock()
+        let group = clients[0].group_create(3, 1, None).await.unwrap();
+        let group_id = string(group, "/group/id");
+        for client in clients.iter_mut().skip(1) {
+            client.group_join(&group_id, None).await.unwrap();
+        }
+
+        let session = clients[0]
+            .session_create(
+                &group_id,
+                JsValue::from_str("keygen").into(),
+                JsValue::NULL,
+                None,
+            )
+            .await
             .unwrap();
-        while pending.len() > 0 {
-            let message = pending.pop_front().unwrap();
-            self.message_channels
-                .offline_protocol_message_tx
-                .send(message)
+        let session_id = string(session, "/session/id");
+        let mut party_numbers = Vec::new();
+        for client in clients.iter_mut() {
+            let signup = client
+                .session_signup(&group_id, &session_id, None)
+                .await
                 .unwrap();
+            party_numbers.push(field(signup, "/partyNumber").as_u64().unwrap() as u16);
         }
+        assert_eq!(party_numbers, [1, 2, 3]);
 
-        receiver
-    }
-
-    fn get_partial_signature_message_receiver(
-        &self,
-    ) -> impl Stream<Item = Result<PartialSignatureNotification>> {
-        // Create receiver stream
-        let receiver = BroadcastStream::new(
-            self.message_channels
-                .partial_signature_message_tx
-                .subscribe(),
+        let keys = future::join_all(
+            clients
+                .iter()
+                .zip(&party_numbers)
+                .map(|(client, party)| client.keygen(&group_id, &session_id, *party, 3, 1)),
         )
-        .map_err(|e| e.into());
+        .await;
+        let keys: Vec<JsValue> = keys.into_iter().map(|key| key.unwrap().into()).collect();
+        let public_key = string(keys[0].clone(), "/publicKey");
+        for key in &keys {
+            assert_eq!(string(key.clone(), "/publicKey"), public_key);
+        }
 
-        // Resend all pending messages
-        let mut pending = self
-            .pending_messages
-            .partial_signature_messages
-            .lock()
+        let session = clients[0]
+            .session_create(
+                &group_id,
+                JsValue::from_str("sign").into(),
+                JsValue::NULL,
+                None,
+            )
+            .await
             .unwrap();
-        while pending.len() > 0 {
-            let message = pending.pop_front().unwrap();
-            self.message_channels
-                .partial_signature_message_tx
-                .send(message)
+        let session_id = string(session, "/session/id");
+        for (client, party) in clients.iter().zip(&party_numbers).take(2) {
+            client
+                .session_login(&group_id, &session_id, *party, None)
+                .await
                 .unwrap();
         }
 
-        receiver
+        let data = [7u8; 32];
+        let signatures =
+            future::join_all(clients.iter().zip(&keys).take(2).map(|(client, key)| {
+                let local_key = js_sys::Reflect::get(key, &JsValue::from_str("localKey")).unwrap();
+                client.sign(
+                    &group_id,
+                    &session_id,
+                    local_key,
+                    vec![1, 2],
+                    &data,
+                    None,
+                    None,
+                )
+            }))
+            .await;
+        for signature in signatures {
+            let signature: JsValue = signature.unwrap().into();
+            let recovered = recover_public_key(
+                &data,
+                &string(signature.clone(), "/r"),
+                &string(signature.clone(), "/s"),
+                field(signature, "/recid").as_u64().unwrap() as u8,
+            )
+            .unwrap();
+            assert_eq!(recovered, public_key);
+        }
     }
 }
@@ -0,0 +1,83 @@
+use super::session_map::SessionMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+/// Who aborted a session.
+#[derive(Clone, Debug)]
+pub enum Abort {
+    /// This party, through `signAbort`.
+    Local,
+    /// Another party, which announced it with the given reason.
+    Peer { party: u16, reason: String },
+}
+
+/// Aborts of the sessions this instance takes part in, kept until the
+/// protocol running on them is done. Aborts may arrive before the protocol
+/// starts, which then fails right away; if it never does, they are forgotten
+/// when the session is closed, or once they are among the oldest past
+/// `DEFAULT_MAX_SESSIONS`.
+#[derive(Default)]
+pub struct Aborts {
+    sessions: Mutex<SessionMap<Abort>>,
+    updated: Notify,
+}
+
+impl Aborts {
+    /// Records the abort of a session, keeping the first one.
+    pub fn abort(&self, group_id: Uuid, session_id: Uuid, abort: Abort) {
+        self.sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert_with((group_id, session_id), || abort);
+        self.updated.notify_waiters();
+    }
+
+    /// Waits until a session is aborted.
+    pub async fn wait(&self, group_id: Uuid, session_id: Uuid) -> Abort {
+        loop {
+            // Created before checking, so aborts in between aren't missed
+            let updated = self.updated.notified();
+            let abort = self
+                .sessions
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(&(group_id, session_id))
+                .cloned();
+            if let Some(abort) = abort {
+                return abort;
+            }
+            updated.await;
+        }
+    }
+
+    /// Forgets the abort of a session.
+    pub fn close_session(&self, group_id: Uuid, session_id: Uuid) {
+        self.sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&(group_id, session_id));
+    }
+
+    /// Returns a guard that forgets the abort of a session when dropped.
+    pub fn session_guard(self: &Arc<Self>, group_id: Uuid, session_id: Uuid) -> AbortGuard {
+        AbortGuard {
+            aborts: self.clone(),
+            group_id,
+            session_id,
+        }
+    }
+}
+
+/// Forgets the abort of a session when dropped.
+pub struct AbortGuard {
+    aborts: Arc<Aborts>,
+    group_id: Uuid,
+    session_id: Uuid,
+}
+
+impl Drop for AbortGuard {
+    fn drop(&mut self) {
+        self.aborts.close_session(self.group_id, self.session_id);
+    }
+}
@@ -0,0 +1,228 @@
+use super::{sequence::SequenceError, MultiPartyEcdsaError};
+#[cfg(feature = "sign")]
+use crate::utils::signers::SignersError;
+use crate::{
+    client::{json_rpc::JsonRpcError, transport::TransportError},
+    utils::{
+        crypto::CryptoError,
+        encryption::EncryptionError,
+        logger::LoggerError,
+        serializer::{format_error_chain, get_json_serializer, serialize_named_rpc_error_to_js},
+        signature::SignatureError,
+        timeout::EnforceTimeoutError,
+    },
+};
+use js_sys::Reflect;
+use serde::Serialize;
+use serde_json::json;
+use wasm_bindgen::{JsCast, JsError, JsValue};
+
+/// Category of a failure, so callers can tell apart the ones worth retrying
+/// from the ones that need different input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCategory {
+    /// The connection to the manager failed or the instance was closed.
+    Network,
+    /// The protocol failed, e.g. a party sent an invalid message.
+    Protocol,
+    /// An argument was rejected before doing anything.
+    InvalidInput,
+    /// A deadline elapsed.
+    Timeout,
+    /// The manager rejected the request.
+    Manager,
+}
+
+/// Error thrown to JS: an `Error` with the `category` of the failure and its
+/// `details`, when there are any.
+#[derive(Debug)]
+pub struct CategorizedError(js_sys::Error);
+
+impl CategorizedError {
+    pub fn new(category: ErrorCategory, message: &str) -> Self {
+        Self(js_sys::Error::new(message)).with_category(category)
+    }
+
+    pub fn invalid_input(error: impl ToString) -> Self {
+        Self::new(ErrorCategory::InvalidInput, &error.to_string())
+    }
+
+    /// Keeps the `code` and `data` of a JSON-RPC error rejected by the
+    /// manager, both as properties and as details, naming the error after
+    /// the matching `MultiPartyEcdsaError` variant if it's a known one for
+    /// the method of the request.
+    pub fn from_rpc_error(method: &str, error: json_rpc_types::Error<serde_json::Value>) -> Self {
+        let details = json!({ "code": error.code.code(), "data": error.data });
+        let (name, message) = match MultiPartyEcdsaError::from_rpc_error(method, &error) {
+            Some(known) => (format!("{:?}", known), known.to_string()),
+            None => ("RpcError".into(), error.message.as_str().to_owned()),
+        };
+        let js_error = serialize_named_rpc_error_to_js(error, &name, &message);
+        Self(js_error.unchecked_into())
+            .with_category(ErrorCategory::Manager)
+            .with_details(details)
+    }
+
+    fn with_category(self, category: ErrorCategory) -> Self {
+        if let Ok(category) = category.serialize(&get_json_serializer()) {
+            let _ = Reflect::set(&self.0, &"category".into(), &category);
+        }
+        self
+    }
+
+    fn with_details(self, details: impl Serialize) -> Self {
+        if let Ok(details) = details.serialize(&get_json_serializer()) {
+            let _ = Reflect::set(&self.0, &"details".into(), &details);
+        }
+        self
+    }
+}
+
+impl From<CategorizedError> for JsValue {
+    fn from(error: CategorizedError) -> Self {
+        error.0.into()
+    }
+}
+
+impl From<MultiPartyEcdsaError> for CategorizedError {
+    fn from(error: MultiPartyEcdsaError) -> Self {
+        let category = match &error {
+            MultiPartyEcdsaError::InvalidGroupId(_)
+            | MultiPartyEcdsaError::InvalidSessionId(_)
+            | MultiPartyEcdsaError::InvalidLocalKey
+            | MultiPartyEcdsaError::InvalidTweak
+            | MultiPartyEcdsaError::CorruptLocalKey
+            | MultiPartyEcdsaError::InvalidParameters { .. } => ErrorCategory::InvalidInput,
+            #[cfg(feature = "sign")]
+            MultiPartyEcdsaError::InvalidHex(_) | MultiPartyEcdsaError::InvalidSignerSet { .. } => {
+                ErrorCategory::InvalidInput
+            }
+            MultiPartyEcdsaError::FailedProtocolExecution(_)
+            | MultiPartyEcdsaError::InconsistentKey
+            | MultiPartyEcdsaError::Aborted
+            | MultiPartyEcdsaError::PeerAborted { .. } => ErrorCategory::Protocol,
+            #[cfg(feature = "sign")]
+            MultiPartyEcdsaError::IncompleteSignature { .. } => ErrorCategory::Protocol,
+            MultiPartyEcdsaError::Timeout | MultiPartyEcdsaError::SessionNotReady => {
+                ErrorCategory::Timeout
+            }
+            MultiPartyEcdsaError::GroupFull
+            | MultiPartyEcdsaError::AlreadySignedUp
+            | MultiPartyEcdsaError::GroupNotFound
+            | MultiPartyEcdsaError::SessionNotFound => ErrorCategory::Manager,
+            MultiPartyEcdsaError::Closed => ErrorCategory::Network,
+        };
+        let js_error = Self::new(category, &format_error_chain(&error));
+        match error {
+            #[cfg(feature = "sign")]
+            MultiPartyEcdsaError::IncompleteSignature { received, expected } => {
+                js_error.with_details(json!({ "received": received, "expected": expected }))
+            }
+            _ => js_error,
+        }
+    }
+}
+
+/// Failures of requests and of the routed protocol messages, told apart by
+/// their cause.
+impl From<anyhow::Error> for CategorizedError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::new(category_of(&error), &error.to_string())
+    }
+}
+
+/// Category of a failure of a request or of the routed protocol messages.
+/// Besides deadlines and the connection failing, they can only fail because
+/// of the messages received from other parties (e.g. malformed or skipped
+/// ones), as the arguments of the caller are checked before.
+fn category_of(error: &anyhow::Error) -> ErrorCategory {
+    if error.is::<EnforceTimeoutError>() || error.is::<SequenceError>() {
+        return ErrorCategory::Timeout;
+    }
+    match error.downcast_ref::<JsonRpcError>() {
+        Some(JsonRpcError::ConnectionClosed | JsonRpcError::Cancelled | JsonRpcError::Closed) => {
+            ErrorCategory::Network
+        }
+        _ if error.is::<TransportError>() => ErrorCategory::Network,
+        _ => ErrorCategory::Protocol,
+    }
+}
+
+/// Serialization errors, which only values given by the caller can cause.
+impl From<JsError> for CategorizedError {
+    fn from(error: JsError) -> Self {
+        let js_error: js_sys::Error = JsValue::from(error).unchecked_into();
+        Self(js_error).with_category(ErrorCategory::InvalidInput)
+    }
+}
+
+impl From<serde_wasm_bindgen::Error> for CategorizedError {
+    fn from(error: serde_wasm_bindgen::Error) -> Self {
+        Self::invalid_input(error)
+    }
+}
+
+impl From<serde_json::Error> for CategorizedError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::invalid_input(error)
+    }
+}
+
+impl From<uuid::Error> for CategorizedError {
+    fn from(error: uuid::Error) -> Self {
+        Self::invalid_input(error)
+    }
+}
+
+impl From<CryptoError> for CategorizedError {
+    fn from(error: CryptoError) -> Self {
+        Self::invalid_input(error)
+    }
+}
+
+impl From<EncryptionError> for CategorizedError {
+    fn from(error: EncryptionError) -> Self {
+        Self::invalid_input(error)
+    }
+}
+
+impl From<LoggerError> for CategorizedError {
+    fn from(error: LoggerError) -> Self {
+        Self::invalid_input(error)
+    }
+}
+
+impl From<SignatureError> for CategorizedError {
+    fn from(error: SignatureError) -> Self {
+        Self::invalid_input(error)
+    }
+}
+
+#[cfg(feature = "sign")]
+impl From<SignersError> for CategorizedError {
+    fn from(error: SignersError) -> Self {
+        Self::invalid_input(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_protocol_failures_from_network_ones() {
+        let malformed = serde_json::from_str::<u16>("not a number").unwrap_err();
+        assert_eq!(category_of(&malformed.into()), ErrorCategory::Protocol);
+        let lagged = JsonRpcError::LaggedReceiver(3);
+        assert_eq!(category_of(&lagged.into()), ErrorCategory::Protocol);
+
+        let closed = JsonRpcError::ConnectionClosed;
+        assert_eq!(category_of(&closed.into()), ErrorCategory::Network);
+        let failed = TransportError::SendingMessage("failed".into());
+        assert_eq!(category_of(&failed.into()), ErrorCategory::Network);
+
+        let missing = SequenceError::MissingMessage(2, 1);
+        assert_eq!(category_of(&missing.into()), ErrorCategory::Timeout);
+    }
+}
@@ -0,0 +1,61 @@
+use super::{error::CategorizedError, router::InspectedMessage, types};
+use crate::utils::serializer::serialize_any_to_js;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use std::pin::Pin;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[wasm_bindgen(inline_js = r#"
+export function toAsyncIterator(inspector) {
+    return {
+        next: () => inspector.next(),
+        return: () => {
+            inspector.free();
+            return Promise.resolve({ done: true, value: undefined });
+        },
+        [Symbol.asyncIterator]() {
+            return this;
+        },
+    };
+}"#)]
+extern "C" {
+    #[wasm_bindgen(js_name = "toAsyncIterator")]
+    fn to_async_iterator(inspector: MessageInspector) -> types::InspectedMessages;
+}
+
+#[derive(Serialize)]
+struct IteratorResult {
+    done: bool,
+    value: Option<InspectedMessage>,
+}
+
+/// Iterator over copies of the protocol messages routed to an instance,
+/// exposed to JS as an async iterator.
+#[wasm_bindgen]
+pub struct MessageInspector {
+    incoming: Pin<Box<dyn Stream<Item = InspectedMessage>>>,
+}
+
+#[wasm_bindgen]
+impl MessageInspector {
+    /// Waits for the next message, following the async iterator protocol.
+    pub async fn next(&mut self) -> Result<types::InspectedMessageResult, CategorizedError> {
+        let value = self.incoming.next().await;
+        let result = IteratorResult {
+            done: value.is_none(),
+            value,
+        };
+        Ok(serialize_any_to_js(result)?.into())
+    }
+}
+
+impl MessageInspector {
+    /// Wraps a stream of messages into a JS async iterator.
+    pub fn into_async_iterator(
+        incoming: impl Stream<Item = InspectedMessage> + 'static,
+    ) -> types::InspectedMessages {
+        to_async_iterator(MessageInspector {
+            incoming: Box::pin(incoming),
+        })
+    }
+}
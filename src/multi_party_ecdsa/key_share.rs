@@ -0,0 +1,70 @@
+use super::{error::CategorizedError, parse_local_key, types, KeygenResponse};
+use crate::utils::{secret::SecretLocalKey, serializer::serialize_any_to_js};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Key share produced by `keygen`, validated and with typed accessors for
+/// its public data.
+///
+/// The key is kept in wasm memory until `free()` is called, which wipes it.
+#[wasm_bindgen]
+pub struct KeyShare {
+    local_key: SecretLocalKey,
+}
+
+#[wasm_bindgen]
+impl KeyShare {
+    /// Wraps the response of `keygen`, or a stored copy of it, failing if
+    /// its local key is invalid or inconsistent. Its public keys are
+    /// computed again from the local key.
+    #[wasm_bindgen(constructor)]
+    pub fn new(response: types::KeygenResponse) -> Result<KeyShare, CategorizedError> {
+        let local_key = js_sys::Reflect::get(&response, &"localKey".into()).unwrap_or_default();
+        Ok(KeyShare {
+            local_key: parse_local_key(local_key)?,
+        })
+    }
+
+    /// The local key, as accepted by `sign`.
+    #[wasm_bindgen(getter, js_name = "localKey")]
+    pub fn local_key(&self) -> Result<types::LocalKey, CategorizedError> {
+        Ok(serialize_any_to_js(&self.local_key)?.into())
+    }
+
+    /// The public key of the group, uncompressed and hex encoded.
+    #[wasm_bindgen(getter, js_name = "publicKey")]
+    pub fn public_key(&self) -> String {
+        hex::encode(self.local_key.public_key().to_bytes(false).as_ref())
+    }
+
+    /// The public key of the group, compressed and hex encoded.
+    #[wasm_bindgen(getter, js_name = "publicKeyCompressed")]
+    pub fn public_key_compressed(&self) -> String {
+        hex::encode(self.local_key.public_key().to_bytes(true).as_ref())
+    }
+
+    /// The party number of this share.
+    #[wasm_bindgen(getter, js_name = "partyIndex")]
+    pub fn party_index(&self) -> u16 {
+        self.local_key.i
+    }
+
+    /// The number of parties of the group.
+    #[wasm_bindgen(getter)]
+    pub fn parties(&self) -> u16 {
+        self.local_key.n
+    }
+
+    /// The threshold of the group.
+    #[wasm_bindgen(getter)]
+    pub fn threshold(&self) -> u16 {
+        self.local_key.t
+    }
+
+    /// Returns the key share as a plain `KeygenResponse` object, without
+    /// timings, e.g. to store it with `JSON.stringify`.
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<types::KeygenResponse, CategorizedError> {
+        let response = KeygenResponse::new(self.local_key.clone());
+        Ok(serialize_any_to_js(response)?.into())
+    }
+}
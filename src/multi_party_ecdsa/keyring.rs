@@ -0,0 +1,252 @@
+use super::session_map::SessionMap;
+use crate::utils::{
+    crypto::{parse_point, CryptoError},
+    encryption::{self, EncryptionError},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use curv::elliptic::curves::{Point, Scalar, Secp256k1};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+use thiserror::Error;
+use tokio::sync::Notify;
+use uuid::Uuid;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Domain of the hash deriving the key shared by two parties of a session.
+const KEY_DERIVATION_DOMAIN: &[u8] = b"wasm-multi-party-ecdsa/session-key/v1";
+
+#[derive(Debug, Error)]
+pub enum KeyringError {
+    #[error("invalid public key of party `{0}`")]
+    InvalidPublicKey(u16, #[source] CryptoError),
+    #[error("no key shared with party `{0}`")]
+    MissingKey(u16),
+    #[error("message is not encrypted for this party")]
+    NotARecipient,
+    #[error("failed to encrypt or decrypt message")]
+    Encryption(#[from] EncryptionError),
+}
+
+/// Message encrypted for its receivers. The message is encrypted with a
+/// random key, which is in turn encrypted for each receiver with the key
+/// shared with it, so broadcasts are sent once.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Encrypted {
+    ciphertext: String,
+    /// Sealed content key of each receiver, by party number. The numbers are
+    /// kept as strings, as `WireMessage` is untagged and the buffered JSON
+    /// it's deserialized from can't parse map keys as integers.
+    keys: BTreeMap<String, String>,
+}
+
+/// Keys of a party in a session.
+#[derive(Default)]
+struct SessionKeys {
+    /// Party number and ephemeral secret of this party, once it joined.
+    secret: Option<(u16, Scalar<Secp256k1>)>,
+    /// Ephemeral public keys of the other parties.
+    public_keys: BTreeMap<u16, Point<Secp256k1>>,
+    /// Keys shared with the other parties.
+    shared_keys: BTreeMap<u16, [u8; 32]>,
+}
+
+impl Drop for SessionKeys {
+    fn drop(&mut self) {
+        self.shared_keys.values_mut().for_each(Zeroize::zeroize);
+    }
+}
+
+impl SessionKeys {
+    /// Derives the key shared with a party, if both ephemeral keys are known.
+    fn derive(&mut self, group_id: Uuid, session_id: Uuid, party: u16) {
+        let (Some((own, secret)), Some(public_key)) =
+            (self.secret.as_ref(), self.public_keys.get(&party))
+        else {
+            return;
+        };
+        let shared_point = public_key * secret;
+        let mut hasher = Sha256::new();
+        hasher.update(KEY_DERIVATION_DOMAIN);
+        hasher.update(group_id.as_bytes());
+        hasher.update(session_id.as_bytes());
+        hasher.update((*own).min(party).to_be_bytes());
+        hasher.update((*own).max(party).to_be_bytes());
+        hasher.update(shared_point.to_bytes(true).as_ref());
+        self.shared_keys.insert(party, hasher.finalize().into());
+    }
+}
+
+/// Ephemeral Diffie-Hellman keys of the sessions this instance takes part
+/// in, used to encrypt the protocol messages end to end.
+///
+/// Public keys of other parties may arrive before this party joins the
+/// session, so they are kept until it does. Keys of sessions whose protocol
+/// never starts are forgotten when the session is closed, or once they are
+/// among the oldest past `DEFAULT_MAX_SESSIONS`.
+#[derive(Default)]
+pub struct Keyring {
+    sessions: Mutex<SessionMap<SessionKeys>>,
+    updated: Notify,
+}
+
+impl Keyring {
+    /// Creates the ephemeral key of this party for a session, returning the
+    /// hex encoded public key to send to the other parties.
+    pub fn join(&self, group_id: Uuid, session_id: Uuid, party_number: u16) -> String {
+        let secret = Scalar::<Secp256k1>::random();
+        let public_key = Point::generator() * &secret;
+        let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        let keys = sessions.get_or_insert_with((group_id, session_id), SessionKeys::default);
+        keys.secret = Some((party_number, secret));
+        keys.shared_keys.values_mut().for_each(Zeroize::zeroize);
+        keys.shared_keys.clear();
+        let parties: Vec<u16> = keys.public_keys.keys().copied().collect();
+        for party in parties {
+            keys.derive(group_id, session_id, party);
+        }
+        self.updated.notify_waiters();
+        hex::encode(public_key.to_bytes(true).as_ref())
+    }
+
+    /// Stores the ephemeral public key sent by another party of a session.
+    pub fn add_public_key(
+        &self,
+        group_id: Uuid,
+        session_id: Uuid,
+        sender: u16,
+        public_key: &str,
+    ) -> Result<(), KeyringError> {
+        let public_key =
+            parse_point(public_key).map_err(|e| KeyringError::InvalidPublicKey(sender, e))?;
+        let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        let keys = sessions.get_or_insert_with((group_id, session_id), SessionKeys::default);
+        keys.public_keys.insert(sender, public_key);
+        keys.derive(group_id, session_id, sender);
+        self.updated.notify_waiters();
+        Ok(())
+    }
+
+    /// Waits until this party shares a key with every given party.
+    pub async fn wait_for(&self, group_id: Uuid, session_id: Uuid, parties: &[u16]) {
+        loop {
+            // Created before checking, so updates in between aren't missed
+            let updated = self.updated.notified();
+            if self.has_keys(group_id, session_id, parties) {
+                return;
+            }
+            updated.await;
+        }
+    }
+
+    fn has_keys(&self, group_id: Uuid, session_id: Uuid, parties: &[u16]) -> bool {
+        let sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        sessions.get(&(group_id, session_id)).is_some_and(|keys| {
+            parties
+                .iter()
+                .all(|party| keys.shared_keys.contains_key(party))
+        })
+    }
+
+    /// Encrypts a message for its receiver, or for every party this party
+    /// shares a key with if it's a broadcast.
+    pub fn encrypt(
+        &self,
+        group_id: Uuid,
+        session_id: Uuid,
+        receiver: Option<u16>,
+        plaintext: &[u8],
+    ) -> Result<Encrypted, KeyringError> {
+        let sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        let shared_keys = sessions
+            .get(&(group_id, session_id))
+            .map(|keys| &keys.shared_keys);
+        let receivers: Vec<u16> = match (receiver, shared_keys) {
+            (Some(receiver), _) => vec![receiver],
+            (None, Some(shared_keys)) => shared_keys.keys().copied().collect(),
+            (None, None) => Vec::new(),
+        };
+
+        let mut content_key = Zeroizing::new([0u8; 32]);
+        getrandom::getrandom(content_key.as_mut()).map_err(|_| EncryptionError::Randomness)?;
+        let mut keys = BTreeMap::new();
+        for receiver in receivers {
+            let shared_key = shared_keys
+                .and_then(|shared_keys| shared_keys.get(&receiver))
+                .ok_or(KeyringError::MissingKey(receiver))?;
+            let sealed = encryption::seal(content_key.as_ref(), shared_key)?;
+            keys.insert(receiver.to_string(), STANDARD.encode(sealed));
+        }
+        let ciphertext = STANDARD.encode(encryption::seal(plaintext, &content_key)?);
+        Ok(Encrypted { ciphertext, keys })
+    }
+
+    /// Decrypts a message sent by another party of a session.
+    pub fn decrypt(
+        &self,
+        group_id: Uuid,
+        session_id: Uuid,
+        sender: u16,
+        encrypted: &Encrypted,
+    ) -> Result<Vec<u8>, KeyringError> {
+        let sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        let keys = sessions.get(&(group_id, session_id));
+        let own = keys
+            .and_then(|keys| keys.secret.as_ref())
+            .map(|(own, _)| *own)
+            .ok_or(KeyringError::MissingKey(sender))?;
+        let shared_key = keys
+            .and_then(|keys| keys.shared_keys.get(&sender))
+            .ok_or(KeyringError::MissingKey(sender))?;
+        let sealed_key = encrypted
+            .keys
+            .get(&own.to_string())
+            .ok_or(KeyringError::NotARecipient)?;
+
+        let sealed_key = STANDARD
+            .decode(sealed_key)
+            .map_err(|_| EncryptionError::InvalidEnvelope)?;
+        let content_key: Zeroizing<[u8; 32]> = encryption::open(&sealed_key, shared_key)?
+            .try_into()
+            .map(Zeroizing::new)
+            .map_err(|_| EncryptionError::InvalidEnvelope)?;
+        let ciphertext = STANDARD
+            .decode(&encrypted.ciphertext)
+            .map_err(|_| EncryptionError::InvalidEnvelope)?;
+        Ok(encryption::open(&ciphertext, &content_key)?)
+    }
+
+    /// Forgets the keys of a session, including the public keys received
+    /// before this party joined it.
+    pub fn close_session(&self, group_id: Uuid, session_id: Uuid) {
+        self.sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&(group_id, session_id));
+    }
+
+    /// Returns a guard that forgets the keys of a session when dropped.
+    pub fn session_guard(self: &Arc<Self>, group_id: Uuid, session_id: Uuid) -> KeyringGuard {
+        KeyringGuard {
+            keyring: self.clone(),
+            group_id,
+            session_id,
+        }
+    }
+}
+
+/// Forgets the keys of a session when dropped.
+pub struct KeyringGuard {
+    keyring: Arc<Keyring>,
+    group_id: Uuid,
+    session_id: Uuid,
+}
+
+impl Drop for KeyringGuard {
+    fn drop(&mut self) {
+        self.keyring.close_session(self.group_id, self.session_id);
+    }
+}
@@ -0,0 +1,184 @@
+use super::{persistence, router::DEFAULT_MAX_PENDING_MESSAGES};
+use crate::client::{
+    json_rpc::{RateLimit, RetryPolicy, DEFAULT_CHANNEL_CAPACITY, DEFAULT_MAX_MESSAGE_SIZE},
+    transport::websocket::ReconnectPolicy,
+};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Default deadline of each phase of the keygen and sign protocols.
+const DEFAULT_PROTOCOL_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Default interval of the rate limit of protocol messages.
+const DEFAULT_RATE_LIMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Options accepted by the `MultiPartyEcdsa` constructor.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Options {
+    /// Maximum number of consecutive reconnection attempts before closing
+    /// for good, unlimited if zero.
+    pub max_reconnect_attempts: Option<u32>,
+    pub reconnect_base_delay_in_ms: Option<u32>,
+    pub reconnect_max_delay_in_ms: Option<u32>,
+    /// Maximum number of times a request failing because of the connection
+    /// is resent, disabled by default.
+    pub request_retries: Option<u32>,
+    pub request_retry_delay_in_ms: Option<u32>,
+    /// Capacity of the notification and protocol message channels.
+    ///
+    /// Every party receives up to `parties - 1` messages per round and a peer
+    /// can be at most one round ahead, so it should be at least
+    /// `2 * (parties - 1)`. Smaller values make receivers lag and drop messages.
+    pub channel_capacity: Option<usize>,
+    /// Maximum size of a received message in bytes, larger ones are dropped.
+    pub max_message_size: Option<usize>,
+    /// Deadline of each phase of the keygen and sign protocols.
+    pub protocol_timeout_in_ms: Option<u32>,
+    /// Deadline to collect the partial signatures of the other signers,
+    /// defaults to the protocol timeout.
+    pub partial_signature_timeout_in_ms: Option<u32>,
+    /// Whether to return the duration of each protocol phase and round
+    /// along with the keygen and sign results.
+    pub with_timings: Option<bool>,
+    /// Maximum number of protocol messages kept while nobody is receiving
+    /// them, evicting the oldest ones past it.
+    pub max_pending_messages: Option<usize>,
+    /// Whether to store pending protocol messages in IndexedDB, so they
+    /// survive page reloads.
+    pub persist_pending_messages: Option<bool>,
+    /// Age after which stored pending messages are discarded.
+    pub pending_messages_max_age_in_ms: Option<u32>,
+    /// Token sent to the host as the `token` query parameter of the url, as
+    /// browsers can't set headers on the websocket handshake.
+    pub auth_token: Option<String>,
+    /// Interval of the keepalive pings, disabled if not set.
+    pub keepalive_interval_in_ms: Option<u32>,
+    /// Whether to compress large protocol messages. Every party must run a
+    /// release that understands compressed messages.
+    pub compress_messages: Option<bool>,
+    /// Whether to encrypt protocol messages end to end, with keys exchanged
+    /// among the parties of each session. Every party must enable it.
+    pub encrypt_messages: Option<bool>,
+    /// Maximum number of protocol messages sent per interval, unlimited if
+    /// not set or zero.
+    pub max_messages_per_interval: Option<u32>,
+    pub rate_limit_interval_in_ms: Option<u32>,
+    /// Whether to check the key produced by keygen against the commitments
+    /// of the group before returning it.
+    pub validate_keygen: Option<bool>,
+}
+
+impl Options {
+    /// Returns the capacity of the notification and message channels.
+    pub fn channel_capacity(&self) -> usize {
+        self.channel_capacity.unwrap_or(DEFAULT_CHANNEL_CAPACITY)
+    }
+
+    /// Returns the maximum size of a received message.
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE)
+    }
+
+    /// Returns the deadline of each protocol phase.
+    pub fn protocol_timeout(&self) -> Duration {
+        self.protocol_timeout_in_ms
+            .map(|t| Duration::from_millis(t.into()))
+            .unwrap_or(DEFAULT_PROTOCOL_TIMEOUT)
+    }
+
+    /// Returns the deadline to collect partial signatures.
+    #[cfg(feature = "sign")]
+    pub fn partial_signature_timeout(&self) -> Duration {
+        self.partial_signature_timeout_in_ms
+            .map(|t| Duration::from_millis(t.into()))
+            .unwrap_or_else(|| self.protocol_timeout())
+    }
+
+    /// Returns the maximum number of pending messages.
+    pub fn max_pending_messages(&self) -> usize {
+        self.max_pending_messages
+            .unwrap_or(DEFAULT_MAX_PENDING_MESSAGES)
+    }
+
+    /// Returns the maximum age of the stored pending messages, if they must
+    /// be persisted.
+    pub fn pending_messages_persistence(&self) -> Option<Duration> {
+        if !self.persist_pending_messages.unwrap_or(false) {
+            return None;
+        }
+        Some(
+            self.pending_messages_max_age_in_ms
+                .map(|t| Duration::from_millis(t.into()))
+                .unwrap_or(persistence::DEFAULT_MAX_AGE),
+        )
+    }
+
+    /// Returns the interval of the keepalive pings, if enabled.
+    pub fn keepalive_interval(&self) -> Option<Duration> {
+        self.keepalive_interval_in_ms
+            .filter(|t| *t > 0)
+            .map(|t| Duration::from_millis(t.into()))
+    }
+
+    /// Returns the url to connect to, including the auth token if any.
+    pub fn url(&self, url: &str) -> String {
+        url_with_token(url, self.auth_token.as_deref())
+    }
+
+    /// Returns the reconnection policy, using defaults for missing values.
+    pub fn reconnect_policy(&self) -> ReconnectPolicy {
+        let default = ReconnectPolicy::default();
+        ReconnectPolicy {
+            max_retries: match self.max_reconnect_attempts {
+                Some(0) => None,
+                Some(max) => Some(max),
+                None => default.max_retries,
+            },
+            base_delay: self
+                .reconnect_base_delay_in_ms
+                .map(|t| Duration::from_millis(t.into()))
+                .unwrap_or(default.base_delay),
+            max_delay: self
+                .reconnect_max_delay_in_ms
+                .map(|t| Duration::from_millis(t.into()))
+                .unwrap_or(default.max_delay),
+        }
+    }
+
+    /// Returns the retry policy of requests, using defaults for missing values.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            max_retries: self.request_retries.unwrap_or(default.max_retries),
+            base_delay: self
+                .request_retry_delay_in_ms
+                .map(|t| Duration::from_millis(t.into()))
+                .unwrap_or(default.base_delay),
+            max_delay: default.max_delay,
+        }
+    }
+
+    /// Returns the rate limit of protocol messages, if enabled.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        let max_messages = self.max_messages_per_interval.filter(|max| *max > 0)?;
+        let interval = self
+            .rate_limit_interval_in_ms
+            .map(|t| Duration::from_millis(t.into()))
+            .unwrap_or(DEFAULT_RATE_LIMIT_INTERVAL);
+        Some(RateLimit {
+            max_messages,
+            interval,
+        })
+    }
+}
+
+/// Appends the auth token, if any, to the query of the url.
+pub fn url_with_token(url: &str, token: Option<&str>) -> String {
+    let Some(token) = token else {
+        return url.into();
+    };
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let token = String::from(js_sys::encode_uri_component(token));
+    format!("{}{}token={}", url, separator, token)
+}
@@ -0,0 +1,101 @@
+use super::router::{MessageRouter, PhaseMessage, RoutedMessage};
+use crate::utils::{
+    idb::IdbStore,
+    serializer::{deserialize_any_from_js, get_json_serializer},
+};
+use anyhow::Result;
+use futures::{channel::mpsc, StreamExt};
+use mpc_manager::service::session_service::SessionMessageNotification;
+use serde::{Deserialize, Serialize};
+use std::{sync::Weak, time::Duration};
+
+const DB_NAME: &str = "wasm-multi-party-ecdsa";
+const STORE_NAME: &str = "pendingMessages";
+
+/// Default age after which stored pending messages are discarded.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Change to the stored pending messages.
+pub enum PersistenceOp {
+    /// Stores a message that was kept as pending.
+    Store(RoutedMessage),
+    /// Removes a message, by key, once it's delivered.
+    Remove(String),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredMessage {
+    key: String,
+    /// Message serialized as JSON, as received from the manager.
+    message: String,
+    /// Milliseconds since the epoch when the message was stored.
+    stored_at: f64,
+}
+
+/// Restores the pending messages stored by a previous instance into the
+/// router, discarding the ones older than `max_age`, and then applies every
+/// change to the pending messages until the router is dropped.
+pub async fn persistence_task(
+    router: Weak<MessageRouter>,
+    mut ops: mpsc::UnboundedReceiver<PersistenceOp>,
+    max_age: Duration,
+) {
+    let store = match IdbStore::open(DB_NAME, STORE_NAME).await {
+        Ok(store) => store,
+        Err(e) => {
+            log::error!("Failed to open pending messages store: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = restore(&store, &router, max_age).await {
+        log::error!("Failed to restore pending messages: {}", e);
+    }
+
+    while let Some(op) = ops.next().await {
+        let result = match op {
+            PersistenceOp::Store(message) => store_message(&store, message).await,
+            PersistenceOp::Remove(key) => store.delete(&key).await,
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to update stored pending messages: {}", e);
+        }
+    }
+}
+
+async fn restore(store: &IdbStore, router: &Weak<MessageRouter>, max_age: Duration) -> Result<()> {
+    let now = js_sys::Date::now();
+    let max_age = max_age.as_millis() as f64;
+    for value in store.get_all().await? {
+        let stored: StoredMessage = deserialize_any_from_js(value)?;
+        if now - stored.stored_at > max_age {
+            log::debug!("Discarding stale pending message {}", stored.key);
+            store.delete(&stored.key).await?;
+            continue;
+        }
+        let message: SessionMessageNotification<PhaseMessage> =
+            match serde_json::from_str(&stored.message) {
+                Ok(message) => message,
+                Err(e) => {
+                    log::warn!("Discarding invalid pending message {}: {}", stored.key, e);
+                    store.delete(&stored.key).await?;
+                    continue;
+                }
+            };
+        let Some(router) = router.upgrade() else { break };
+        router.restore(message.into());
+    }
+    Ok(())
+}
+
+async fn store_message(store: &IdbStore, message: RoutedMessage) -> Result<()> {
+    let key = message.key();
+    let message: SessionMessageNotification<PhaseMessage> = message.into();
+    let stored = StoredMessage {
+        key: key.clone(),
+        message: serde_json::to_string(&message)?,
+        stored_at: js_sys::Date::now(),
+    };
+    let value = stored.serialize(&get_json_serializer())?;
+    store.put(&key, &value).await
+}
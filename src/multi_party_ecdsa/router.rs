@@ -0,0 +1,776 @@
+use super::{
+    keyring::Encrypted, persistence::PersistenceOp, sequence::Sequenced, session_map::SessionMap,
+};
+use crate::utils::compression;
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::{channel::mpsc, future, stream, Stream, StreamExt, TryStreamExt};
+use mpc_manager::service::session_service::SessionMessageNotification;
+#[cfg(feature = "keygen")]
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::keygen::ProtocolMessage;
+#[cfg(feature = "sign")]
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::sign::{
+    OfflineProtocolMessage, PartialSignature,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, PoisonError},
+};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use uuid::Uuid;
+
+/// Version of the format of the protocol messages exchanged through the
+/// manager, bumped on incompatible changes.
+pub const PROTOCOL_VERSION: &str = "1";
+
+/// Minimum size of the JSON of a protocol message for it to be compressed,
+/// as deflate barely shrinks small ones.
+const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Default maximum number of pending messages kept by a router.
+pub const DEFAULT_MAX_PENDING_MESSAGES: usize = 1024;
+
+#[cfg(feature = "keygen")]
+pub type ProtocolMessageNotification =
+    SessionMessageNotification<Sequenced<round_based::Msg<ProtocolMessage>>>;
+#[cfg(feature = "sign")]
+pub type OfflineProtocolMessageNotification =
+    SessionMessageNotification<Sequenced<round_based::Msg<OfflineProtocolMessage>>>;
+#[cfg(feature = "sign")]
+pub type PartialSignatureNotification =
+    SessionMessageNotification<Sequenced<round_based::Msg<PartialSignature>>>;
+
+/// Protocol message exchanged through the manager, tagged with the phase it
+/// belongs to so it can be routed without guessing its type.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "phase", content = "message", rename_all = "camelCase")]
+pub enum PhaseMessage {
+    #[cfg(feature = "keygen")]
+    Keygen(Sequenced<round_based::Msg<ProtocolMessage>>),
+    #[cfg(feature = "sign")]
+    Offline(Sequenced<round_based::Msg<OfflineProtocolMessage>>),
+    #[cfg(feature = "sign")]
+    Partial(Sequenced<round_based::Msg<PartialSignature>>),
+}
+
+/// Protocol message as sent through the manager, compressed if it's large
+/// and compression is enabled by the sender, and encrypted if encryption is.
+/// Every form is always accepted.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WireMessage {
+    /// Deflated `PhaseMessage` JSON, base64 encoded.
+    Compressed {
+        compressed: String,
+    },
+    /// Hex encoded ephemeral public key of the sender, to derive the keys
+    /// encrypting the messages of the session.
+    Handshake {
+        handshake: String,
+    },
+    /// Announces the sender aborted the session, with the given reason.
+    Abort {
+        abort: String,
+    },
+    /// `WireMessage` JSON, either compressed or not, encrypted for its
+    /// receivers.
+    Encrypted {
+        encrypted: Encrypted,
+    },
+    Plain(PhaseMessage),
+}
+
+impl WireMessage {
+    /// Encodes a message to be sent, compressing it if enabled and its JSON
+    /// is at least `COMPRESSION_THRESHOLD` bytes long. It's sent as is if
+    /// compression fails, e.g. when the environment doesn't support it.
+    pub async fn encode(message: PhaseMessage, compress: bool) -> Result<Self> {
+        if !compress {
+            return Ok(WireMessage::Plain(message));
+        }
+        let json = serde_json::to_vec(&message)?;
+        if json.len() < COMPRESSION_THRESHOLD {
+            return Ok(WireMessage::Plain(message));
+        }
+        match compression::compress(&json).await {
+            Ok(compressed) => Ok(WireMessage::Compressed {
+                compressed: STANDARD.encode(compressed),
+            }),
+            Err(e) => {
+                log::warn!("Failed to compress message, sending it uncompressed: {}", e);
+                Ok(WireMessage::Plain(message))
+            }
+        }
+    }
+
+    /// Decodes a received message, decompressing it if needed up to
+    /// `max_size` bytes. Handshakes, aborts and encrypted messages must be
+    /// handled before.
+    pub async fn decode(self, max_size: usize) -> Result<PhaseMessage> {
+        match self {
+            WireMessage::Plain(message) => Ok(message),
+            WireMessage::Compressed { compressed } => {
+                let compressed = STANDARD.decode(compressed)?;
+                let json = compression::decompress(&compressed, max_size).await?;
+                Ok(serde_json::from_slice(&json)?)
+            }
+            WireMessage::Handshake { .. }
+            | WireMessage::Abort { .. }
+            | WireMessage::Encrypted { .. } => Err(anyhow::anyhow!("not a protocol message")),
+        }
+    }
+}
+
+/// Kind of a routed message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    #[cfg(feature = "keygen")]
+    Protocol,
+    #[cfg(feature = "sign")]
+    Offline,
+    #[cfg(feature = "sign")]
+    Partial,
+}
+
+/// Message received from the manager, ready to be consumed by a protocol.
+#[derive(Clone, Debug)]
+pub enum RoutedMessage {
+    #[cfg(feature = "keygen")]
+    Protocol(ProtocolMessageNotification),
+    #[cfg(feature = "sign")]
+    Offline(OfflineProtocolMessageNotification),
+    #[cfg(feature = "sign")]
+    Partial(PartialSignatureNotification),
+}
+
+impl RoutedMessage {
+    /// Returns an identifier of the message, unique among the messages
+    /// received in its session.
+    pub fn key(&self) -> String {
+        fn key<B>(
+            notification: &SessionMessageNotification<Sequenced<round_based::Msg<B>>>,
+            phase: &str,
+        ) -> String {
+            // Broadcasts and point-to-point messages are numbered separately
+            let receiver = match notification.message.message.receiver {
+                Some(receiver) => receiver.to_string(),
+                None => "all".into(),
+            };
+            format!(
+                "{}/{}/{}/{}/{}/{}",
+                notification.group_id,
+                notification.session_id,
+                notification.sender,
+                phase,
+                receiver,
+                notification.message.seq
+            )
+        }
+        match self {
+            #[cfg(feature = "keygen")]
+            RoutedMessage::Protocol(message) => key(message, "keygen"),
+            #[cfg(feature = "sign")]
+            RoutedMessage::Offline(message) => key(message, "offline"),
+            #[cfg(feature = "sign")]
+            RoutedMessage::Partial(message) => key(message, "partial"),
+        }
+    }
+
+    /// Returns the group and session ids of the message.
+    pub fn session(&self) -> (Uuid, Uuid) {
+        match self {
+            #[cfg(feature = "keygen")]
+            RoutedMessage::Protocol(message) => (message.group_id, message.session_id),
+            #[cfg(feature = "sign")]
+            RoutedMessage::Offline(message) => (message.group_id, message.session_id),
+            #[cfg(feature = "sign")]
+            RoutedMessage::Partial(message) => (message.group_id, message.session_id),
+        }
+    }
+
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            #[cfg(feature = "keygen")]
+            RoutedMessage::Protocol(_) => MessageKind::Protocol,
+            #[cfg(feature = "sign")]
+            RoutedMessage::Offline(_) => MessageKind::Offline,
+            #[cfg(feature = "sign")]
+            RoutedMessage::Partial(_) => MessageKind::Partial,
+        }
+    }
+}
+
+impl From<SessionMessageNotification<PhaseMessage>> for RoutedMessage {
+    fn from(notification: SessionMessageNotification<PhaseMessage>) -> Self {
+        let SessionMessageNotification {
+            group_id,
+            session_id,
+            sender,
+            message,
+        } = notification;
+        match message {
+            #[cfg(feature = "keygen")]
+            PhaseMessage::Keygen(message) => RoutedMessage::Protocol(SessionMessageNotification {
+                group_id,
+                session_id,
+                sender,
+                message,
+            }),
+            #[cfg(feature = "sign")]
+            PhaseMessage::Offline(message) => RoutedMessage::Offline(SessionMessageNotification {
+                group_id,
+                session_id,
+                sender,
+                message,
+            }),
+            #[cfg(feature = "sign")]
+            PhaseMessage::Partial(message) => RoutedMessage::Partial(SessionMessageNotification {
+                group_id,
+                session_id,
+                sender,
+                message,
+            }),
+        }
+    }
+}
+
+impl From<RoutedMessage> for SessionMessageNotification<PhaseMessage> {
+    fn from(message: RoutedMessage) -> Self {
+        match message {
+            #[cfg(feature = "keygen")]
+            RoutedMessage::Protocol(notification) => SessionMessageNotification {
+                group_id: notification.group_id,
+                session_id: notification.session_id,
+                sender: notification.sender,
+                message: PhaseMessage::Keygen(notification.message),
+            },
+            #[cfg(feature = "sign")]
+            RoutedMessage::Offline(notification) => SessionMessageNotification {
+                group_id: notification.group_id,
+                session_id: notification.session_id,
+                sender: notification.sender,
+                message: PhaseMessage::Offline(notification.message),
+            },
+            #[cfg(feature = "sign")]
+            RoutedMessage::Partial(notification) => SessionMessageNotification {
+                group_id: notification.group_id,
+                session_id: notification.session_id,
+                sender: notification.sender,
+                message: PhaseMessage::Partial(notification.message),
+            },
+        }
+    }
+}
+
+/// Copy of a routed message for observability, with its body serialized.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InspectedMessage {
+    group_id: Uuid,
+    session_id: Uuid,
+    sender: u16,
+    receiver: Option<u16>,
+    phase: &'static str,
+    seq: u64,
+    body: serde_json::Value,
+}
+
+impl InspectedMessage {
+    fn new<T: Serialize>(
+        notification: &SessionMessageNotification<Sequenced<round_based::Msg<T>>>,
+        phase: &'static str,
+    ) -> serde_json::Result<Self> {
+        Ok(Self {
+            group_id: notification.group_id,
+            session_id: notification.session_id,
+            sender: notification.sender,
+            receiver: notification.message.message.receiver,
+            phase,
+            seq: notification.message.seq,
+            body: serde_json::to_value(&notification.message.message.body)?,
+        })
+    }
+}
+
+impl TryFrom<&RoutedMessage> for InspectedMessage {
+    type Error = serde_json::Error;
+
+    fn try_from(message: &RoutedMessage) -> serde_json::Result<Self> {
+        match message {
+            #[cfg(feature = "keygen")]
+            RoutedMessage::Protocol(message) => InspectedMessage::new(message, "keygen"),
+            #[cfg(feature = "sign")]
+            RoutedMessage::Offline(message) => InspectedMessage::new(message, "offline"),
+            #[cfg(feature = "sign")]
+            RoutedMessage::Partial(message) => InspectedMessage::new(message, "partial"),
+        }
+    }
+}
+
+/// Notification that can be extracted from a `RoutedMessage`.
+pub trait FromRoutedMessage: Sized {
+    const KIND: MessageKind;
+
+    fn from_routed_message(message: RoutedMessage) -> Option<Self>;
+}
+
+#[cfg(feature = "keygen")]
+impl FromRoutedMessage for ProtocolMessageNotification {
+    const KIND: MessageKind = MessageKind::Protocol;
+
+    fn from_routed_message(message: RoutedMessage) -> Option<Self> {
+        match message {
+            RoutedMessage::Protocol(message) => Some(message),
+            #[allow(unreachable_patterns)] // Keygen-only builds have no other kind
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "sign")]
+impl FromRoutedMessage for OfflineProtocolMessageNotification {
+    const KIND: MessageKind = MessageKind::Offline;
+
+    fn from_routed_message(message: RoutedMessage) -> Option<Self> {
+        match message {
+            RoutedMessage::Offline(message) => Some(message),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "sign")]
+impl FromRoutedMessage for PartialSignatureNotification {
+    const KIND: MessageKind = MessageKind::Partial;
+
+    fn from_routed_message(message: RoutedMessage) -> Option<Self> {
+        match message {
+            RoutedMessage::Partial(message) => Some(message),
+            _ => None,
+        }
+    }
+}
+
+/// Channel of the messages of a given kind of a session.
+type ChannelKey = (Uuid, Uuid, MessageKind);
+
+/// Routes received messages to the protocols consuming them.
+///
+/// Every session has its own channel for each kind of message, so protocols
+/// running concurrently on different sessions never see each other's
+/// messages. Messages nobody is listening for are kept as pending and
+/// replayed once a receiver for their session and kind is created, evicting
+/// the oldest ones past `max_pending`. When persistence is enabled, pending
+/// messages are stored until they are replayed or evicted. Messages of
+/// closed sessions are ignored, as long as they are among the most recently
+/// closed ones; older ones are forgotten so they don't pile up.
+///
+/// Its locks are only taken for synchronous updates, so routing never waits
+/// on a receiver being created and vice versa.
+pub struct MessageRouter {
+    channels: Mutex<HashMap<ChannelKey, broadcast::Sender<RoutedMessage>>>,
+    capacity: usize,
+    tap_tx: broadcast::Sender<RoutedMessage>,
+    pending: Mutex<VecDeque<RoutedMessage>>,
+    max_pending: usize,
+    closed_sessions: Mutex<SessionMap<()>>,
+    persistence: Option<mpsc::UnboundedSender<PersistenceOp>>,
+}
+
+impl MessageRouter {
+    /// Creates a new `MessageRouter`, sending the changes of its pending
+    /// messages to `persistence` if given.
+    pub fn new(
+        capacity: usize,
+        max_pending: usize,
+        persistence: Option<mpsc::UnboundedSender<PersistenceOp>>,
+    ) -> Self {
+        Self {
+            channels: Mutex::new(HashMap::new()),
+            capacity,
+            tap_tx: broadcast::channel::<RoutedMessage>(capacity).0,
+            pending: Mutex::new(VecDeque::new()),
+            max_pending,
+            closed_sessions: Mutex::new(SessionMap::default()),
+            persistence,
+        }
+    }
+
+    /// Sends a message to the receivers of its session and kind, keeping it
+    /// as pending if there are none.
+    pub fn route(&self, message: RoutedMessage) {
+        if self.tap_tx.receiver_count() > 0 {
+            let _ = self.tap_tx.send(message.clone()); // Ignores no receiver error
+        }
+        if self.is_closed(&message) {
+            log::debug!("Ignoring message {} of a closed session", message.key());
+            return;
+        }
+        self.deliver(message, false);
+    }
+
+    /// Routes a message restored from persistence, which is already stored.
+    pub fn restore(&self, message: RoutedMessage) {
+        if self.is_closed(&message) {
+            self.persist(PersistenceOp::Remove(message.key()));
+            return;
+        }
+        self.deliver(message, true);
+    }
+
+    /// Closes a session, dropping its channels and pending messages and
+    /// ignoring the ones received from now on.
+    pub fn close_session(&self, group_id: Uuid, session_id: Uuid) {
+        self.closed_sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert_with((group_id, session_id), || ());
+        self.channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|(group, session, _), _| (*group, *session) != (group_id, session_id));
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|message| {
+                if message.session() != (group_id, session_id) {
+                    return true;
+                }
+                self.persist(PersistenceOp::Remove(message.key()));
+                false
+            });
+    }
+
+    /// Drops every pending message, keeping the stored ones.
+    pub fn clear_pending(&self) {
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// Returns a guard that closes the given session when dropped.
+    pub fn session_guard(self: &Arc<Self>, group_id: Uuid, session_id: Uuid) -> SessionGuard {
+        SessionGuard {
+            router: self.clone(),
+            group_id,
+            session_id,
+        }
+    }
+
+    fn is_closed(&self, message: &RoutedMessage) -> bool {
+        self.closed_sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(&message.session())
+    }
+
+    fn deliver(&self, message: RoutedMessage, stored: bool) {
+        // Receivers are checked under the lock `subscribe` registers them
+        // with, so a message is either replayed to a new receiver or sent
+        // live to it, never left pending
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        let (group_id, session_id) = message.session();
+        let tx = self
+            .channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&(group_id, session_id, message.kind()))
+            .filter(|tx| tx.receiver_count() > 0)
+            .cloned();
+        let Some(tx) = tx else {
+            self.keep_pending(&mut pending, message, stored);
+            return;
+        };
+        let key = stored.then(|| message.key());
+        match tx.send(message) {
+            Ok(_) => {
+                if let Some(key) = key {
+                    self.persist(PersistenceOp::Remove(key));
+                }
+            }
+            Err(broadcast::error::SendError(message)) => {
+                self.keep_pending(&mut pending, message, stored)
+            }
+        }
+    }
+
+    fn keep_pending(
+        &self,
+        pending: &mut VecDeque<RoutedMessage>,
+        message: RoutedMessage,
+        stored: bool,
+    ) {
+        if !stored && self.persistence.is_some() {
+            self.persist(PersistenceOp::Store(message.clone()));
+        }
+        pending.push_back(message);
+        while pending.len() > self.max_pending {
+            let Some(evicted) = pending.pop_front() else { break };
+            log::warn!(
+                "Too many pending messages, dropping message {}",
+                evicted.key()
+            );
+            self.persist(PersistenceOp::Remove(evicted.key()));
+        }
+    }
+
+    fn persist(&self, op: PersistenceOp) {
+        if let Some(persistence) = self.persistence.as_ref() {
+            let _ = persistence.unbounded_send(op); // Ignores stopped persistence task
+        }
+    }
+
+    /// Counts the pending messages of each kind.
+    pub fn pending_counts(&self) -> PendingCounts {
+        let pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut counts = PendingCounts::default();
+        for message in pending.iter() {
+            match message.kind() {
+                #[cfg(feature = "keygen")]
+                MessageKind::Protocol => counts.protocol += 1,
+                #[cfg(feature = "sign")]
+                MessageKind::Offline => counts.offline += 1,
+                #[cfg(feature = "sign")]
+                MessageKind::Partial => counts.partial += 1,
+            }
+        }
+        counts
+    }
+
+    /// Creates a stream observing every routed message, without consuming
+    /// them. It ends when the router is dropped.
+    pub fn tap(&self) -> impl Stream<Item = Result<RoutedMessage>> {
+        BroadcastStream::new(self.tap_tx.subscribe()).map_err(|e| e.into())
+    }
+
+    /// Creates a receiver stream for the messages of a given kind of a
+    /// session.
+    ///
+    /// Pending messages of that session and kind are delivered first, in the
+    /// order they were received, ahead of the live ones.
+    pub fn subscribe<T>(&self, group_id: Uuid, session_id: Uuid) -> impl Stream<Item = Result<T>>
+    where
+        T: FromRoutedMessage + 'static,
+    {
+        // Subscribe and take the pending messages at once, so every message
+        // routed from now on is received live after them
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        let rx = self
+            .channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry((group_id, session_id, T::KIND))
+            .or_insert_with(|| broadcast::channel(self.capacity).0)
+            .subscribe();
+        let live = BroadcastStream::new(rx).map_err(|e| e.into());
+        let (replay, rest) = pending.drain(..).partition::<VecDeque<_>, _>(|message| {
+            message.session() == (group_id, session_id) && message.kind() == T::KIND
+        });
+        *pending = rest;
+        for message in replay.iter() {
+            self.persist(PersistenceOp::Remove(message.key()));
+        }
+
+        stream::iter(replay.into_iter().map(Ok))
+            .chain(live)
+            .try_filter_map(|message| future::ready(Ok(T::from_routed_message(message))))
+    }
+}
+
+/// Number of pending messages of each kind.
+#[derive(Debug, Default)]
+pub struct PendingCounts {
+    pub protocol: usize,
+    pub offline: usize,
+    pub partial: usize,
+}
+
+/// Closes a session when dropped, so its stray messages are released once
+/// the protocol running on it is done, whatever the outcome.
+pub struct SessionGuard {
+    router: Arc<MessageRouter>,
+    group_id: Uuid,
+    session_id: Uuid,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.router.close_session(self.group_id, self.session_id);
+    }
+}
+
+#[cfg(all(test, feature = "keygen"))]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::keygen::Keygen;
+    use round_based::{Msg, StateMachine};
+
+    /// Broadcasts of party 1 in the first two rounds of a 2-of-3 keygen.
+    fn keygen_rounds() -> (Msg<ProtocolMessage>, Msg<ProtocolMessage>) {
+        let mut parties: Vec<Keygen> = (1..=3).map(|i| Keygen::new(i, 1, 3).unwrap()).collect();
+        let round1: Vec<_> = parties
+            .iter_mut()
+            .flat_map(|party| std::mem::take(party.message_queue()))
+            .collect();
+        let party = &mut parties[0];
+        for message in round1.iter().filter(|message| message.sender != 1) {
+            party.handle_incoming(message.clone()).unwrap();
+        }
+        if party.wants_to_proceed() {
+            party.proceed().unwrap();
+        }
+        let round2 = party.message_queue().remove(0);
+        (round1[0].clone(), round2)
+    }
+
+    fn routed(
+        (group_id, session_id): (Uuid, Uuid),
+        message: &Msg<ProtocolMessage>,
+        seq: u64,
+    ) -> RoutedMessage {
+        RoutedMessage::Protocol(SessionMessageNotification {
+            group_id,
+            session_id,
+            sender: message.sender,
+            message: Sequenced {
+                seq,
+                message: message.clone(),
+            },
+        })
+    }
+
+    /// Returns the numbers of the messages received so far.
+    fn received<S>(messages: &mut S) -> Vec<u64>
+    where
+        S: Stream<Item = Result<ProtocolMessageNotification>> + Unpin,
+    {
+        let mut received = Vec::new();
+        while let Some(Some(message)) = messages.next().now_or_never() {
+            received.push(message.unwrap().message.seq);
+        }
+        received
+    }
+
+    #[test]
+    fn delivers_pending_messages_before_live_ones() {
+        let (round1, round2) = keygen_rounds();
+        let router = MessageRouter::new(16, 16, None);
+        let session = (Uuid::new_v4(), Uuid::new_v4());
+
+        router.route(routed(session, &round1, 0));
+        assert_eq!(router.pending_counts().protocol, 1);
+        let mut messages = Box::pin(router.subscribe(session.0, session.1));
+        router.route(routed(session, &round2, 1));
+
+        assert_eq!(received(&mut messages), [0, 1]);
+        assert_eq!(router.pending_counts().protocol, 0);
+    }
+
+    #[test]
+    fn keeps_messages_pending_once_the_receivers_are_dropped() {
+        let (round1, round2) = keygen_rounds();
+        let router = MessageRouter::new(16, 16, None);
+        let session = (Uuid::new_v4(), Uuid::new_v4());
+
+        let mut messages = Box::pin(router.subscribe(session.0, session.1));
+        router.route(routed(session, &round1, 0));
+        assert_eq!(received(&mut messages), [0]);
+        drop(messages);
+
+        // The channel is still there, but a message without receivers must
+        // be replayed to the next one rather than lost
+        router.route(routed(session, &round2, 1));
+        assert_eq!(router.pending_counts().protocol, 1);
+        let mut messages = Box::pin(router.subscribe(session.0, session.1));
+        assert_eq!(received(&mut messages), [1]);
+    }
+
+    #[test]
+    fn keeps_concurrent_sessions_apart() {
+        let (round1, round2) = keygen_rounds();
+        let router = MessageRouter::new(16, 16, None);
+        let group_id = Uuid::new_v4();
+        let first = (group_id, Uuid::new_v4());
+        let second = (group_id, Uuid::new_v4());
+
+        // Messages of the second session are numbered from 10, to tell them
+        // apart. Pending messages are only replayed to their own session.
+        router.route(routed(second, &round1, 10));
+        let mut first_messages = Box::pin(router.subscribe(first.0, first.1));
+        assert!(received(&mut first_messages).is_empty());
+        let mut second_messages = Box::pin(router.subscribe(second.0, second.1));
+
+        router.route(routed(first, &round1, 0));
+        router.route(routed(second, &round2, 11));
+        router.route(routed(first, &round2, 1));
+        assert_eq!(received(&mut first_messages), [0, 1]);
+        assert_eq!(received(&mut second_messages), [10, 11]);
+
+        // Closing one session leaves the other running
+        router.close_session(first.0, first.1);
+        router.route(routed(first, &round1, 2));
+        router.route(routed(second, &round1, 12));
+        assert_eq!(received(&mut second_messages), [12]);
+        assert_eq!(router.pending_counts().protocol, 0);
+    }
+
+    fn round_trip(message: &WireMessage) -> WireMessage {
+        serde_json::from_str(&serde_json::to_string(message).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn wrapped_messages_deserialize_to_their_own_variant() {
+        let compressed = WireMessage::Compressed {
+            compressed: "eJwDAAAAAAE=".into(),
+        };
+        assert!(matches!(
+            round_trip(&compressed),
+            WireMessage::Compressed { compressed } if compressed == "eJwDAAAAAAE="
+        ));
+
+        let handshake = WireMessage::Handshake {
+            handshake: "02aa".into(),
+        };
+        assert!(matches!(
+            round_trip(&handshake),
+            WireMessage::Handshake { handshake } if handshake == "02aa"
+        ));
+
+        let abort = WireMessage::Abort {
+            abort: "cancelled".into(),
+        };
+        assert!(matches!(
+            round_trip(&abort),
+            WireMessage::Abort { abort } if abort == "cancelled"
+        ));
+
+        let encrypted = WireMessage::Encrypted {
+            encrypted: serde_json::from_value(serde_json::json!({
+                "ciphertext": "00ff",
+                "keys": { "2": "aa", "3": "bb" },
+            }))
+            .unwrap(),
+        };
+        let json = serde_json::to_value(&encrypted).unwrap();
+        let decoded = round_trip(&encrypted);
+        assert!(matches!(decoded, WireMessage::Encrypted { .. }));
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), json);
+    }
+
+    #[test]
+    fn protocol_messages_deserialize_as_plain_ones() {
+        let (round1, _) = keygen_rounds();
+        let plain = WireMessage::Plain(PhaseMessage::Keygen(Sequenced {
+            seq: 3,
+            message: round1,
+        }));
+        assert!(matches!(
+            round_trip(&plain),
+            WireMessage::Plain(PhaseMessage::Keygen(Sequenced { seq: 3, .. }))
+        ));
+    }
+}
@@ -0,0 +1,318 @@
+use crate::utils::timeout::enforce_timeout;
+use anyhow::Result;
+use futures::{stream, Stream, StreamExt};
+use mpc_manager::service::session_service::SessionMessageNotification;
+use round_based::Msg;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    pin::Pin,
+    time::Duration,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Maximum time to wait for a missing message before failing.
+pub const MESSAGE_GAP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum SequenceError {
+    #[error("message `{1}` from party `{0}` never arrived")]
+    MissingMessage(u16, u64),
+}
+
+/// Message tagged with its position in the sequence of messages sent by a
+/// party during a protocol phase to the same receiver, or broadcast.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sequenced<T> {
+    pub seq: u64,
+    pub message: T,
+}
+
+/// Numbers the messages sent by a party during a protocol phase.
+///
+/// The manager only delivers a point-to-point message to its receiver, so
+/// broadcasts and the messages to each party are numbered separately, and
+/// every receiver sees a sequence without gaps.
+#[derive(Default)]
+pub struct Sequencer {
+    next: HashMap<Option<u16>, u64>,
+}
+
+impl Sequencer {
+    /// Tags a message with the next number of its stream.
+    pub fn sequence<B>(&mut self, message: Msg<B>) -> Sequenced<Msg<B>> {
+        let next = self.next.entry(message.receiver).or_default();
+        let seq = *next;
+        *next += 1;
+        Sequenced { seq, message }
+    }
+}
+
+/// Stream of messages of a sender, either its broadcasts or the ones it
+/// sends to a given party.
+type StreamKey = (Uuid, Uuid, u16, Option<u16>);
+
+struct StreamState<B> {
+    next: u64,
+    buffered: BTreeMap<u64, SessionMessageNotification<Msg<B>>>,
+}
+
+impl<B> StreamState<B> {
+    /// Whether a message of the stream was already received. Messages
+    /// before the next expected one or already buffered are retransmits, so
+    /// tracking them takes constant space per stream, however long the
+    /// session is. Numbers are only compared within a stream, as a
+    /// broadcast and a point-to-point message may share one.
+    fn is_duplicate(&self, seq: u64) -> bool {
+        seq < self.next || self.buffered.contains_key(&seq)
+    }
+}
+
+struct Reorderer<S, B> {
+    incoming: Pin<Box<S>>,
+    streams: HashMap<StreamKey, StreamState<B>>,
+    ready: VecDeque<SessionMessageNotification<Msg<B>>>,
+    done: bool,
+}
+
+impl<S, B> Reorderer<S, B> {
+    /// Buffers a message, moving to the ready queue every message of its
+    /// stream that is now in sequence. Duplicated messages are dropped.
+    fn push(&mut self, notification: SessionMessageNotification<Sequenced<Msg<B>>>) {
+        let SessionMessageNotification {
+            group_id,
+            session_id,
+            sender,
+            message: Sequenced { seq, message },
+        } = notification;
+        let key = (group_id, session_id, sender, message.receiver);
+        let notification = SessionMessageNotification {
+            group_id,
+            session_id,
+            sender,
+            message,
+        };
+
+        let stream = self.streams.entry(key).or_insert_with(|| StreamState {
+            next: 0,
+            buffered: BTreeMap::new(),
+        });
+        if stream.is_duplicate(seq) {
+            let receiver = notification.message.receiver;
+            log::debug!(
+                "Dropping duplicated message {} from party {} to {:?}",
+                seq,
+                sender,
+                receiver
+            );
+            return;
+        }
+        stream.buffered.insert(seq, notification);
+        while let Some(notification) = stream.buffered.remove(&stream.next) {
+            self.ready.push_back(notification);
+            stream.next += 1;
+        }
+    }
+
+    /// Returns the sender and sequence number of a missing message, if any.
+    fn first_gap(&self) -> Option<(u16, u64)> {
+        self.streams
+            .iter()
+            .find(|(_, state)| !state.buffered.is_empty())
+            .map(|((_, _, sender, _), state)| (*sender, state.next))
+    }
+}
+
+/// Delivers the messages of every sender in the order they were sent,
+/// dropping duplicates. Broadcasts and point-to-point messages are ordered
+/// separately, as they are numbered.
+///
+/// Messages received ahead of time are held until the missing ones arrive,
+/// failing if they don't arrive within `gap_timeout`.
+pub fn in_sequence<S, B>(
+    incoming: S,
+    gap_timeout: Duration,
+) -> impl Stream<Item = Result<SessionMessageNotification<Msg<B>>>>
+where
+    S: Stream<Item = Result<SessionMessageNotification<Sequenced<Msg<B>>>>>,
+{
+    let reorderer = Reorderer {
+        incoming: Box::pin(incoming),
+        streams: HashMap::new(),
+        ready: VecDeque::new(),
+        done: false,
+    };
+
+    stream::unfold(reorderer, move |mut reorderer| async move {
+        loop {
+            if let Some(notification) = reorderer.ready.pop_front() {
+                return Some((Ok(notification), reorderer));
+            }
+            if reorderer.done {
+                return None;
+            }
+
+            let next = match reorderer.first_gap() {
+                Some((sender, seq)) => {
+                    match enforce_timeout(gap_timeout, reorderer.incoming.next()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            reorderer.done = true;
+                            let err = SequenceError::MissingMessage(sender, seq);
+                            return Some((Err(err.into()), reorderer));
+                        }
+                    }
+                }
+                None => reorderer.incoming.next().await,
+            };
+            match next? {
+                Ok(notification) => reorderer.push(notification),
+                Err(e) => return Some((Err(e), reorderer)),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::timeout::timeout;
+    use futures::{channel::mpsc, pin_mut, TryStreamExt};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    type Notification = SessionMessageNotification<Sequenced<Msg<u64>>>;
+
+    fn message(sender: u16, receiver: Option<u16>, seq: u64) -> Notification {
+        SessionMessageNotification {
+            group_id: Uuid::nil(),
+            session_id: Uuid::nil(),
+            sender,
+            message: Sequenced {
+                seq,
+                message: Msg {
+                    sender,
+                    receiver,
+                    body: seq,
+                },
+            },
+        }
+    }
+
+    fn reorderer() -> Reorderer<stream::Empty<Result<Notification>>, u64> {
+        Reorderer {
+            incoming: Box::pin(stream::empty()),
+            streams: HashMap::new(),
+            ready: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn ready(reorderer: &mut Reorderer<stream::Empty<Result<Notification>>, u64>) -> Vec<u64> {
+        reorderer
+            .ready
+            .drain(..)
+            .map(|notification| notification.message.body)
+            .collect()
+    }
+
+    #[test]
+    fn numbers_broadcasts_and_each_receiver_separately() {
+        let mut sequencer = Sequencer::default();
+        let seqs: Vec<u64> = [None, Some(2), Some(3), None, Some(2), Some(3)]
+            .into_iter()
+            .map(|receiver| {
+                let message = Msg {
+                    sender: 1,
+                    receiver,
+                    body: (),
+                };
+                sequencer.sequence(message).seq
+            })
+            .collect();
+        assert_eq!(seqs, [0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn point_to_point_messages_to_other_parties_leave_no_gap() {
+        // Party 2 of 3 only receives the broadcasts of party 1 and the
+        // messages party 1 sends to it, never the ones sent to party 3
+        let mut reorderer = reorderer();
+        reorderer.push(message(1, None, 0));
+        reorderer.push(message(1, Some(2), 0));
+        reorderer.push(message(1, None, 1));
+        reorderer.push(message(1, Some(2), 1));
+        assert_eq!(ready(&mut reorderer), [0, 0, 1, 1]);
+        assert!(reorderer.first_gap().is_none());
+    }
+
+    #[test]
+    fn holds_messages_received_ahead_of_time() {
+        let mut reorderer = reorderer();
+        reorderer.push(message(1, Some(2), 1));
+        reorderer.push(message(1, None, 0));
+        assert_eq!(ready(&mut reorderer), [0]);
+        assert_eq!(reorderer.first_gap(), Some((1, 0)));
+        reorderer.push(message(1, Some(2), 0));
+        assert_eq!(ready(&mut reorderer), [0, 1]);
+        assert!(reorderer.first_gap().is_none());
+    }
+
+    #[test]
+    fn drops_duplicated_messages() {
+        let mut reorderer = reorderer();
+        reorderer.push(message(1, None, 0));
+        reorderer.push(message(1, None, 0));
+        assert_eq!(ready(&mut reorderer), [0]);
+
+        // Also while buffered, waiting for a missing message
+        reorderer.push(message(1, None, 2));
+        reorderer.push(message(1, None, 2));
+        reorderer.push(message(1, None, 1));
+        assert_eq!(ready(&mut reorderer), [1, 2]);
+    }
+
+    #[test]
+    fn same_number_in_another_stream_is_not_a_duplicate() {
+        let mut reorderer = reorderer();
+        reorderer.push(message(1, None, 0));
+        reorderer.push(message(1, Some(2), 0));
+        reorderer.push(message(3, None, 0));
+        assert_eq!(ready(&mut reorderer), [0, 0, 0]);
+    }
+
+    #[wasm_bindgen_test]
+    async fn delivers_buffered_messages_once_the_gap_is_filled() {
+        let (tx, rx) = mpsc::unbounded();
+        let incoming = in_sequence(rx.map(Ok::<_, anyhow::Error>), Duration::from_secs(1));
+        pin_mut!(incoming);
+        tx.unbounded_send(message(1, None, 1)).unwrap();
+        wasm_bindgen_futures::spawn_local(async move {
+            timeout(Duration::from_millis(10)).await;
+            tx.unbounded_send(message(1, None, 0)).unwrap();
+        });
+
+        let received: Vec<u64> = incoming
+            .take(2)
+            .map_ok(|notification| notification.message.body)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(received, [0, 1]);
+    }
+
+    #[wasm_bindgen_test]
+    async fn fails_when_the_gap_is_not_filled_in_time() {
+        let (tx, rx) = mpsc::unbounded();
+        let incoming = in_sequence(rx.map(Ok::<_, anyhow::Error>), Duration::from_millis(10));
+        pin_mut!(incoming);
+        tx.unbounded_send(message(1, None, 1)).unwrap();
+
+        let err = incoming.try_next().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(SequenceError::MissingMessage(1, 0))
+        ));
+        drop(tx);
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::{HashMap, VecDeque};
+use uuid::Uuid;
+
+/// Default maximum number of sessions remembered by a `SessionMap`.
+pub const DEFAULT_MAX_SESSIONS: usize = 1024;
+
+/// Group and session ids of a session.
+pub type SessionId = (Uuid, Uuid);
+
+/// Values kept per session, forgetting the sessions added first once there
+/// are more than `capacity`, so the ones never cleaned up (e.g. sessions
+/// whose protocol is never started) don't pile up.
+pub struct SessionMap<V> {
+    values: HashMap<SessionId, V>,
+    order: VecDeque<SessionId>,
+    capacity: usize,
+}
+
+impl<V> Default for SessionMap<V> {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_SESSIONS)
+    }
+}
+
+impl<V> SessionMap<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            values: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn contains(&self, session: &SessionId) -> bool {
+        self.values.contains_key(session)
+    }
+
+    pub fn get(&self, session: &SessionId) -> Option<&V> {
+        self.values.get(session)
+    }
+
+    /// Returns the value of a session, adding it if missing and forgetting
+    /// the oldest sessions to make room for it.
+    pub fn get_or_insert_with(&mut self, session: SessionId, value: impl FnOnce() -> V) -> &mut V {
+        if !self.values.contains_key(&session) {
+            while self.values.len() >= self.capacity.max(1) {
+                let Some(oldest) = self.order.pop_front() else { break };
+                self.values.remove(&oldest);
+            }
+            self.order.push_back(session);
+        }
+        self.values.entry(session).or_insert_with(value)
+    }
+
+    pub fn remove(&mut self, session: &SessionId) -> Option<V> {
+        let value = self.values.remove(session)?;
+        self.order.retain(|s| s != session);
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_the_oldest_sessions_past_the_capacity() {
+        let sessions: Vec<SessionId> = (0..4).map(|_| (Uuid::new_v4(), Uuid::new_v4())).collect();
+        let mut map = SessionMap::new(3);
+        for (i, session) in sessions.iter().enumerate() {
+            map.get_or_insert_with(*session, || i);
+        }
+        assert!(!map.contains(&sessions[0]));
+        assert!(sessions[1..].iter().all(|session| map.contains(session)));
+
+        // Existing sessions keep their value and place
+        assert_eq!(*map.get_or_insert_with(sessions[1], || 10), 1);
+        map.get_or_insert_with(sessions[0], || 0);
+        assert!(!map.contains(&sessions[1]));
+        assert_eq!(map.get(&sessions[2]), Some(&2));
+
+        // Removed sessions leave room for new ones
+        assert_eq!(map.remove(&sessions[2]), Some(2));
+        map.get_or_insert_with(sessions[1], || 1);
+        assert!(map.contains(&sessions[3]));
+    }
+}
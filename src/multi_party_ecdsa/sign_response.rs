@@ -0,0 +1,183 @@
+use super::{error::CategorizedError, types};
+use crate::utils::{
+    serializer::{deserialize_any_from_js, serialize_any_to_js},
+    signature::{eip155_v, legacy_v, to_der, to_fixed_bytes, SignatureError},
+};
+use curv::{
+    arithmetic::Converter,
+    elliptic::curves::{Scalar, Secp256k1},
+    BigInt,
+};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[derive(Serialize, Deserialize)]
+struct SignResponse {
+    r: String,
+    s: String,
+    recid: u8,
+}
+
+/// Signature produced by `sign`, validated and with accessors for its
+/// encodings.
+#[wasm_bindgen]
+pub struct Signature {
+    r: BigInt,
+    s: BigInt,
+    recid: u8,
+}
+
+#[wasm_bindgen]
+impl Signature {
+    /// Wraps the response of `sign`, failing if it isn't a valid signature.
+    #[wasm_bindgen(constructor)]
+    pub fn new(response: types::SignResponse) -> Result<Signature, CategorizedError> {
+        let response: SignResponse = deserialize_any_from_js(response.into())?;
+        if response.recid > 3 {
+            return Err(SignatureError::InvalidRecoveryId(response.recid).into());
+        }
+        let r = BigInt::from_hex(&response.r).map_err(|_| SignatureError::InvalidSignature)?;
+        let s = BigInt::from_hex(&response.s).map_err(|_| SignatureError::InvalidSignature)?;
+        let order = Scalar::<Secp256k1>::group_order();
+        if r.is_zero() || s.is_zero() || &r >= order || &s >= order {
+            return Err(SignatureError::InvalidSignature.into());
+        }
+
+        Ok(Signature {
+            r,
+            s,
+            recid: response.recid,
+        })
+    }
+
+    /// The `r` value, hex encoded.
+    #[wasm_bindgen(getter)]
+    pub fn r(&self) -> String {
+        self.r.to_hex()
+    }
+
+    /// The `s` value, hex encoded.
+    #[wasm_bindgen(getter)]
+    pub fn s(&self) -> String {
+        self.s.to_hex()
+    }
+
+    /// The recovery id, between 0 and 3.
+    #[wasm_bindgen(getter)]
+    pub fn recid(&self) -> u8 {
+        self.recid
+    }
+
+    /// Returns the DER encoding, hex encoded, as used by Bitcoin.
+    #[wasm_bindgen(js_name = "toDerHex")]
+    pub fn to_der_hex(&self) -> String {
+        hex::encode(to_der(&self.r, &self.s))
+    }
+
+    /// Returns the 64-byte compact signature `r || s`, hex encoded, with
+    /// both values left padded to 32 bytes.
+    #[wasm_bindgen(js_name = "toCompactHex")]
+    pub fn to_compact_hex(&self) -> String {
+        let mut bytes = Vec::with_capacity(64);
+        bytes.extend(to_fixed_bytes(&self.r));
+        bytes.extend(to_fixed_bytes(&self.s));
+        hex::encode(bytes)
+    }
+
+    /// Returns the 65-byte recoverable signature `r || s || recid`, hex
+    /// encoded, with both values left padded to 32 bytes.
+    #[wasm_bindgen(js_name = "toRecoverableHex")]
+    pub fn to_recoverable_hex(&self) -> String {
+        let mut bytes = Vec::with_capacity(65);
+        bytes.extend(to_fixed_bytes(&self.r));
+        bytes.extend(to_fixed_bytes(&self.s));
+        bytes.push(self.recid);
+        hex::encode(bytes)
+    }
+
+    /// Returns `r || s || v`, hex encoded with a `0x` prefix, where `v` is
+    /// the recovery id plus 27, as used by Ethereum. Fails for recovery ids
+    /// 2 and 3, which Ethereum can't encode.
+    #[wasm_bindgen(js_name = "toEthereumHex")]
+    pub fn to_ethereum_hex(&self) -> Result<String, CategorizedError> {
+        let v = legacy_v(self.recid)?;
+        let mut bytes = Vec::with_capacity(65);
+        bytes.extend(to_fixed_bytes(&self.r));
+        bytes.extend(to_fixed_bytes(&self.s));
+        bytes.push(v as u8);
+        Ok(format!("0x{}", hex::encode(bytes)))
+    }
+
+    /// Returns the `v` value of an Ethereum signature. Given a chain id,
+    /// it's `recid + chainId * 2 + 35`, as EIP-155 transactions require;
+    /// otherwise it's the legacy `recid + 27`, as used by pre-EIP-155
+    /// transactions and signed messages.
+    #[wasm_bindgen(js_name = "ethereumV")]
+    pub fn ethereum_v(&self, chain_id: Option<f64>) -> Result<f64, CategorizedError> {
+        let v = match chain_id {
+            Some(chain_id) => {
+                if chain_id.fract() != 0.0 || !(1.0..=u64::MAX as f64).contains(&chain_id) {
+                    return Err(SignatureError::InvalidChainId(chain_id.to_string()).into());
+                }
+                eip155_v(self.recid, chain_id as u64)?
+            }
+            None => legacy_v(self.recid)?,
+        };
+        Ok(v as f64)
+    }
+
+    /// Returns the signature as a plain `SignResponse` object.
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<types::SignResponse, CategorizedError> {
+        let response = SignResponse {
+            r: self.r(),
+            s: self.s(),
+            recid: self.recid,
+        };
+        Ok(serialize_any_to_js(response)?.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn signature(r: &str, s: &str, recid: u8) -> Signature {
+        Signature {
+            r: BigInt::from_hex(r).unwrap(),
+            s: BigInt::from_hex(s).unwrap(),
+            recid,
+        }
+    }
+
+    #[test]
+    fn pads_leading_zero_values_in_fixed_size_encodings() {
+        let signature = signature(&"ab".repeat(31), "1ff", 1);
+        let r = format!("00{}", "ab".repeat(31));
+        let s = format!("{}01ff", "00".repeat(30));
+        assert_eq!(signature.to_compact_hex(), format!("{}{}", r, s));
+        assert_eq!(signature.to_recoverable_hex(), format!("{}{}01", r, s));
+        assert_eq!(
+            signature.to_ethereum_hex().unwrap(),
+            format!("0x{}{}1c", r, s)
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn rejects_recovery_ids_ethereum_cannot_encode() {
+        for recid in [2, 3] {
+            assert!(signature("1", "1", recid).to_ethereum_hex().is_err());
+        }
+    }
+
+    #[test]
+    fn encodes_der_with_minimal_integers() {
+        let signature = signature(&"ab".repeat(31), "1ff", 1);
+        let r = format!("00{}", "ab".repeat(31));
+        assert_eq!(
+            signature.to_der_hex(),
+            format!("3026021f{}020201ff", &r[2..])
+        );
+    }
+}
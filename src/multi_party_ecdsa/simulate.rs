@@ -0,0 +1,230 @@
+use super::{error::CategorizedError, parse_local_key, types, KeygenResponse, SignResponse};
+use super::{validate_keygen_parameters, MultiPartyEcdsaError};
+use crate::utils::{
+    secret::SecretLocalKey,
+    serializer::serialize_any_to_js,
+    signers::{normalize_signers, validate_signers},
+};
+use curv::{arithmetic::Converter, BigInt};
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::party_i::SignatureRecid;
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::{
+    keygen::{Keygen, LocalKey},
+    sign::{CompletedOfflineStage, OfflineStage, SignManual},
+};
+use round_based::{Msg, StateMachine};
+use thiserror::Error;
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+
+#[derive(Debug, Error)]
+enum SimulationError {
+    #[error("no party can proceed at round {0}")]
+    Stalled(u16),
+    #[error("party `{0}` finished without output")]
+    MissingOutput(u16),
+}
+
+/// Runs the state machines of every party to completion in this thread,
+/// delivering their messages to each other in memory.
+fn run<SM>(mut parties: Vec<SM>) -> Result<Vec<SM::Output>, MultiPartyEcdsaError>
+where
+    SM: StateMachine,
+    SM::MessageBody: Clone,
+    SM::Err: std::error::Error + 'static,
+{
+    let failed = |e: SM::Err| MultiPartyEcdsaError::FailedProtocolExecution(Box::new(e));
+    while !parties.iter().all(|party| party.is_finished()) {
+        let sent: Vec<Msg<SM::MessageBody>> = parties
+            .iter_mut()
+            .flat_map(|party| std::mem::take(party.message_queue()))
+            .collect();
+        let mut progressed = !sent.is_empty();
+        for message in sent {
+            for party in parties.iter_mut() {
+                let index = party.party_ind();
+                if index == message.sender || message.receiver.is_some_and(|r| r != index) {
+                    continue;
+                }
+                party.handle_incoming(message.clone()).map_err(failed)?;
+            }
+        }
+        for party in parties.iter_mut() {
+            if party.wants_to_proceed() {
+                party.proceed().map_err(failed)?;
+                progressed = true;
+            }
+        }
+        if !progressed {
+            let round = parties.first().map_or(0, |party| party.current_round());
+            return Err(MultiPartyEcdsaError::FailedProtocolExecution(Box::new(
+                SimulationError::Stalled(round),
+            )));
+        }
+    }
+    parties
+        .iter_mut()
+        .map(|party| match party.pick_output() {
+            Some(output) => output.map_err(failed),
+            None => Err(MultiPartyEcdsaError::FailedProtocolExecution(Box::new(
+                SimulationError::MissingOutput(party.party_ind()),
+            ))),
+        })
+        .collect()
+}
+
+/// Runs a keygen of `parties` parties with the given threshold inside this
+/// module, without any manager, returning the response of every party
+/// ordered by party number. Meant for demos and tests: every secret share
+/// ends up in the same place.
+#[wasm_bindgen(js_name = "simulateKeygen")]
+pub fn simulate_keygen(
+    parties: u16,
+    threshold: u16,
+) -> Result<types::KeygenResponses, CategorizedError> {
+    validate_keygen_parameters(1, parties, threshold)?;
+    let keygens = (1..=parties)
+        .map(|i| Keygen::new(i, threshold, parties))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(CategorizedError::invalid_input)?;
+
+    let array = js_sys::Array::new();
+    for local_key in run(keygens)? {
+        let response = KeygenResponse::new(SecretLocalKey::new(local_key));
+        array.push(&serialize_any_to_js(response)?);
+    }
+    Ok(JsValue::from(array).into())
+}
+
+/// Creates the offline stage of every signer, from its local key. Each
+/// stage takes its own copy of the key, which `OfflineStage` doesn't wipe;
+/// only the keys given, which wipe themselves, are in reach.
+fn offline_stages(
+    local_keys: &[SecretLocalKey],
+    signers: &[u16],
+) -> Result<Vec<OfflineStage>, MultiPartyEcdsaError> {
+    let mut stages = Vec::with_capacity(signers.len());
+    for (index, signer) in (1..).zip(signers) {
+        let local_key = local_keys
+            .iter()
+            .find(|local_key| local_key.i == *signer)
+            .ok_or_else(|| MultiPartyEcdsaError::InvalidSignerSet {
+                reason: format!("missing the local key of party `{}`", signer),
+            })?;
+        validate_signers(signers, local_key.i, local_key.t, local_key.n).map_err(|e| {
+            MultiPartyEcdsaError::InvalidSignerSet {
+                reason: e.to_string(),
+            }
+        })?;
+        let stage = OfflineStage::new(index, signers.to_vec(), LocalKey::clone(local_key))
+            .map_err(|e| MultiPartyEcdsaError::FailedProtocolExecution(Box::new(e)))?;
+        stages.push(stage);
+    }
+    Ok(stages)
+}
+
+/// Completes the signature of `message` from the offline stages of every
+/// signer, as each of them would with the partial signatures of the others.
+fn sign(
+    completed_offline_stages: Vec<CompletedOfflineStage>,
+    message: &BigInt,
+) -> Result<SignatureRecid, MultiPartyEcdsaError> {
+    let mut manuals = Vec::with_capacity(completed_offline_stages.len());
+    let mut partial_signatures = Vec::with_capacity(completed_offline_stages.len());
+    for completed_offline_stage in completed_offline_stages {
+        let (manual, partial_signature) = SignManual::new(message.clone(), completed_offline_stage)
+            .map_err(|e| MultiPartyEcdsaError::FailedProtocolExecution(Box::new(e)))?;
+        manuals.push(manual);
+        partial_signatures.push(partial_signature);
+    }
+
+    // Every signer completes with the partial signatures of the others, so
+    // the first one's signature is theirs too
+    let Some(manual) = manuals.into_iter().next() else {
+        return Err(MultiPartyEcdsaError::InvalidSignerSet {
+            reason: "no signers given".into(),
+        });
+    };
+    manual
+        .complete(&partial_signatures[1..])
+        .map_err(|e| MultiPartyEcdsaError::FailedProtocolExecution(Box::new(e)))
+}
+
+/// Signs data with the local keys of the given signers inside this module,
+/// as `sign` would with every signer online. `localKeys` are the keys of
+/// the group, e.g. as returned by `simulateKeygen`, of which only the
+/// signers' are used.
+#[wasm_bindgen(js_name = "simulateSign")]
+pub fn simulate_sign(
+    local_keys: Vec<JsValue>,
+    data_to_sign: &[u8],
+    signers: Vec<u16>,
+) -> Result<types::SignResponse, CategorizedError> {
+    let local_keys = local_keys
+        .into_iter()
+        .map(parse_local_key)
+        .collect::<Result<Vec<_>, _>>()?;
+    let signers = normalize_signers(&signers);
+    let stages = offline_stages(&local_keys, &signers)?;
+    // Wiped right away, as the stages hold the copies they need
+    drop(local_keys);
+    let signature = sign(run(stages)?, &BigInt::from_bytes(data_to_sign))?;
+    let response = SignResponse {
+        signature,
+        timings: None,
+    };
+    Ok(serialize_any_to_js(&response)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{derivation::derive_child, signature::recover_public_key};
+    use curv::elliptic::curves::{Point, Scalar, Secp256k1};
+
+    fn keygen(parties: u16, threshold: u16) -> Vec<SecretLocalKey> {
+        let keygens = (1..=parties)
+            .map(|i| Keygen::new(i, threshold, parties).unwrap())
+            .collect();
+        run(keygens)
+            .unwrap()
+            .into_iter()
+            .map(SecretLocalKey::new)
+            .collect()
+    }
+
+    #[test]
+    fn signs_with_any_two_parties_of_three() {
+        let local_keys = keygen(3, 1);
+        let public_key = local_keys[0].public_key();
+        assert!(local_keys.iter().all(|key| key.public_key() == public_key));
+
+        let message = BigInt::from_bytes(&[7; 32]);
+        for signers in [[1, 2], [1, 3], [2, 3]] {
+            let stages = offline_stages(&local_keys, &signers).unwrap();
+            let signature = sign(run(stages).unwrap(), &message).unwrap();
+            let (r, s) = (signature.r.to_bigint(), signature.s.to_bigint());
+            let recovered = recover_public_key(&message, &r, &s, signature.recid).unwrap();
+            assert_eq!(recovered, public_key);
+        }
+    }
+
+    #[test]
+    fn signs_with_derived_child_keys() {
+        let mut child_keys = keygen(3, 1);
+        let tweak = Scalar::<Secp256k1>::from_bigint(&BigInt::from(42u32));
+        let child_public_key = child_keys[0].public_key() + Point::generator() * &tweak;
+
+        for child_key in child_keys.iter_mut() {
+            derive_child(child_key, &tweak);
+        }
+        for child_key in &child_keys {
+            assert_eq!(child_key.public_key(), child_public_key);
+        }
+
+        let message = BigInt::from_bytes(&[7; 32]);
+        let stages = offline_stages(&child_keys, &[1, 3]).unwrap();
+        let signature = sign(run(stages).unwrap(), &message).unwrap();
+        let (r, s) = (signature.r.to_bigint(), signature.s.to_bigint());
+        let recovered = recover_public_key(&message, &r, &s, signature.recid).unwrap();
+        assert_eq!(recovered, child_public_key);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::utils::serializer::serialize_any_to_js;
+use anyhow::Result;
+use futures::{channel::oneshot, future, pin_mut, select, FutureExt, Stream, StreamExt};
+use serde::Serialize;
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+
+/// Handle of a callback registered for notifications, to stop calling it.
+#[wasm_bindgen]
+pub struct Subscription {
+    unsubscribe_tx: Option<oneshot::Sender<()>>,
+}
+
+#[wasm_bindgen]
+impl Subscription {
+    /// Stops calling the callback. Dropping the handle doesn't, so callbacks
+    /// registered without keeping it are called for as long as the instance
+    /// lives.
+    #[wasm_bindgen]
+    pub fn unsubscribe(&mut self) {
+        if let Some(unsubscribe_tx) = self.unsubscribe_tx.take() {
+            let _ = unsubscribe_tx.send(()); // Ignores already stopped listener
+        }
+    }
+}
+
+/// Calls the callback with every item of the stream until unsubscribed, or
+/// only with the first one if `once` is set.
+pub fn listen<T>(
+    incoming: impl Stream<Item = Result<T>> + 'static,
+    callback: js_sys::Function,
+    once: bool,
+) -> Subscription
+where
+    T: Serialize,
+{
+    let (unsubscribe_tx, unsubscribe_rx) = oneshot::channel::<()>();
+
+    wasm_bindgen_futures::spawn_local(async move {
+        // Only an explicit unsubscribe stops the listener, not dropping the
+        // handle, which JS may garbage collect at any time
+        let unsubscribed = async move {
+            if unsubscribe_rx.await.is_err() {
+                future::pending::<()>().await;
+            }
+        }
+        .fuse();
+        let incoming = incoming.fuse();
+        pin_mut!(unsubscribed);
+        pin_mut!(incoming);
+
+        loop {
+            let msg = select! {
+                msg = incoming.next() => msg,
+                _ = unsubscribed => break,
+            };
+            let Some(msg) = msg else { break };
+            let Ok(msg) = msg else { continue };
+            let Ok(msg) = serialize_any_to_js(msg) else { continue };
+            if let Err(e) = callback.call1(&JsValue::NULL, &msg) {
+                log::error!("Notification callback failed: {:?}", e);
+            }
+            if once {
+                break;
+            }
+        }
+    });
+
+    Subscription {
+        unsubscribe_tx: Some(unsubscribe_tx),
+    }
+}
@@ -0,0 +1,178 @@
+use round_based::{Msg, StateMachine};
+use serde::Serialize;
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc, time::Duration};
+
+/// Duration of a protocol phase and of each of its rounds.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseTiming {
+    phase: &'static str,
+    duration_ms: f64,
+    rounds_ms: Vec<f64>,
+    peer_latencies_ms: Vec<BTreeMap<u16, f64>>,
+}
+
+/// Durations of the rounds of a phase, and the time each peer took to
+/// send its messages within each round.
+#[derive(Debug, Default)]
+struct Rounds {
+    durations_ms: Vec<f64>,
+    peer_latencies_ms: Vec<BTreeMap<u16, f64>>,
+}
+
+/// Durations of the phases of a protocol run, recorded only if enabled.
+pub struct Timings {
+    phases: Option<Vec<PhaseTiming>>,
+}
+
+impl Timings {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            phases: enabled.then(Vec::new),
+        }
+    }
+
+    /// Starts timing a new phase.
+    pub fn start(&self) -> PhaseTimer {
+        PhaseTimer {
+            start: js_sys::Date::now(),
+            rounds: self.phases.as_ref().map(|_| Default::default()),
+        }
+    }
+
+    /// Records a finished phase.
+    pub fn finish(&mut self, phase: &'static str, timer: PhaseTimer) {
+        let Some(phases) = self.phases.as_mut() else { return };
+        let rounds = timer.rounds.map(|rounds| rounds.take()).unwrap_or_default();
+        phases.push(PhaseTiming {
+            phase,
+            duration_ms: js_sys::Date::now() - timer.start,
+            rounds_ms: rounds.durations_ms,
+            peer_latencies_ms: rounds.peer_latencies_ms,
+        });
+    }
+
+    /// Returns the recorded phases, if enabled.
+    pub fn into_phases(self) -> Option<Vec<PhaseTiming>> {
+        self.phases
+    }
+}
+
+/// Timer of a phase in progress.
+pub struct PhaseTimer {
+    start: f64,
+    rounds: Option<Rc<RefCell<Rounds>>>,
+}
+
+impl PhaseTimer {
+    /// Wraps the state machine running the phase to time its rounds.
+    pub fn timed<SM: StateMachine>(&self, state: SM) -> Timed<SM> {
+        Timed {
+            round: state.current_round(),
+            round_start: self.start,
+            arrivals: BTreeMap::new(),
+            rounds: self.rounds.clone(),
+            state,
+        }
+    }
+}
+
+/// State machine recording the duration of each of its rounds, measured
+/// from the end of the previous one, when this party sent its messages.
+///
+/// The latency of a peer in a round is the time from the start of the round
+/// to the arrival of its last message during it, so the slowest peer is the
+/// one holding the round back.
+#[derive(Debug)]
+pub struct Timed<SM> {
+    state: SM,
+    round: u16,
+    round_start: f64,
+    arrivals: BTreeMap<u16, f64>,
+    rounds: Option<Rc<RefCell<Rounds>>>,
+}
+
+impl<SM: StateMachine> Timed<SM> {
+    fn record_arrival(&mut self, sender: u16) {
+        if self.rounds.is_none() {
+            return;
+        }
+        let latency = js_sys::Date::now() - self.round_start;
+        self.arrivals.insert(sender, latency);
+    }
+
+    fn record_round(&mut self) {
+        let Some(rounds) = self.rounds.as_ref() else { return };
+        let round = self.state.current_round();
+        if round == self.round {
+            return;
+        }
+        let now = js_sys::Date::now();
+        let mut rounds = rounds.borrow_mut();
+        rounds.durations_ms.push(now - self.round_start);
+        rounds
+            .peer_latencies_ms
+            .push(std::mem::take(&mut self.arrivals));
+        self.round = round;
+        self.round_start = now;
+    }
+}
+
+impl<SM: StateMachine> StateMachine for Timed<SM> {
+    type MessageBody = SM::MessageBody;
+    type Err = SM::Err;
+    type Output = SM::Output;
+
+    fn handle_incoming(&mut self, msg: Msg<Self::MessageBody>) -> Result<(), Self::Err> {
+        self.record_arrival(msg.sender);
+        let result = self.state.handle_incoming(msg);
+        self.record_round();
+        result
+    }
+
+    fn message_queue(&mut self) -> &mut Vec<Msg<Self::MessageBody>> {
+        self.state.message_queue()
+    }
+
+    fn wants_to_proceed(&self) -> bool {
+        self.state.wants_to_proceed()
+    }
+
+    fn proceed(&mut self) -> Result<(), Self::Err> {
+        let result = self.state.proceed();
+        self.record_round();
+        result
+    }
+
+    fn round_timeout(&self) -> Option<Duration> {
+        self.state.round_timeout()
+    }
+
+    fn round_timeout_reached(&mut self) -> Self::Err {
+        self.state.round_timeout_reached()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.state.is_finished()
+    }
+
+    fn pick_output(&mut self) -> Option<Result<Self::Output, Self::Err>> {
+        self.state.pick_output()
+    }
+
+    fn current_round(&self) -> u16 {
+        self.state.current_round()
+    }
+
+    fn total_rounds(&self) -> Option<u16> {
+        self.state.total_rounds()
+    }
+
+    fn party_ind(&self) -> u16 {
+        self.state.party_ind()
+    }
+
+    fn parties(&self) -> u16 {
+        self.state.parties()
+    }
+}
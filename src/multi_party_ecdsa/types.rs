@@ -3,6 +3,47 @@ use wasm_bindgen::prelude::wasm_bindgen;
 #[wasm_bindgen(typescript_custom_section)]
 const SESSION_KIND: &'static str = r#"type SessionKind = "keygen" | "sign";"#;
 
+#[wasm_bindgen(typescript_custom_section)]
+const CONNECTION_STATE: &'static str =
+    r#"type ConnectionState = "connecting" | "open" | "closing" | "closed";"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const MULTI_PARTY_ECDSA_OPTIONS: &'static str = r#"
+interface MultiPartyEcdsaOptions {
+    maxReconnectAttempts?: number;
+    reconnectBaseDelayInMs?: number;
+    reconnectMaxDelayInMs?: number;
+    requestRetries?: number;
+    requestRetryDelayInMs?: number;
+    channelCapacity?: number;
+    maxMessageSize?: number;
+    protocolTimeoutInMs?: number;
+    partialSignatureTimeoutInMs?: number;
+    withTimings?: boolean;
+    maxPendingMessages?: number;
+    persistPendingMessages?: boolean;
+    pendingMessagesMaxAgeInMs?: number;
+    authToken?: string;
+    keepaliveIntervalInMs?: number;
+    compressMessages?: boolean;
+    encryptMessages?: boolean;
+    maxMessagesPerInterval?: number;
+    rateLimitIntervalInMs?: number;
+    validateKeygen?: boolean;
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const WEB_SOCKET_LIKE: &'static str = r#"
+interface WebSocketLike {
+    readyState: number;
+    send(data: string): void;
+    addEventListener(type: string, listener: (event: any) => void): void;
+    onmessage: ((event: { data: any }) => void) | null;
+    onopen: ((event: any) => void) | null;
+    onerror: ((event: any) => void) | null;
+    onclose: ((event: { code: number; reason: string }) => void) | null;
+}"#;
+
 #[wasm_bindgen(typescript_custom_section)]
 const GROUP: &'static str = r#"
 interface Group {
@@ -52,26 +93,213 @@ interface SessionLoginResponse {
     session: Session;
 }"#;
 
+#[wasm_bindgen(typescript_custom_section)]
+const SESSION_READY_NOTIFICATION: &'static str = r#"
+interface SessionReadyNotification {
+    groupId: string;
+    sessionId: string;
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const ERRORS: &'static str = r#"
+type ErrorCategory = "network" | "protocol" | "invalidInput" | "timeout" | "manager";
+
+interface NetworkError extends Error {
+    category: "network";
+}
+
+interface ProtocolError extends Error {
+    category: "protocol";
+    details?: {
+        received: number;
+        expected: number;
+    };
+}
+
+interface InvalidInputError extends Error {
+    category: "invalidInput";
+}
+
+interface TimeoutError extends Error {
+    category: "timeout";
+}
+
+interface RpcError extends Error {
+    category: "manager";
+    code: number;
+    message: string;
+    data?: any;
+    details: {
+        code: number;
+        data?: any;
+    };
+}
+
+type MultiPartyEcdsaError =
+    | NetworkError
+    | ProtocolError
+    | InvalidInputError
+    | TimeoutError
+    | RpcError;"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const CONNECTION_EVENTS: &'static str = r#"
+interface ConnectionError {
+    message: string;
+}
+
+interface DisconnectReason {
+    code: number;
+    reason: string;
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const INSPECTED_MESSAGE: &'static str = r#"
+type ProtocolPhase = "keygen" | "offline" | "partial";
+
+interface InspectedMessage {
+    groupId: string;
+    sessionId: string;
+    sender: number;
+    receiver: number | null;
+    phase: ProtocolPhase;
+    seq: number;
+    body: any;
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const PHASE_TIMING: &'static str = r#"
+interface PhaseTiming {
+    phase: "keygen" | "offline" | "online";
+    durationMs: number;
+    roundsMs: number[];
+    peerLatenciesMs: Record<number, number>[];
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const LOCAL_KEY: &'static str = r#"
+interface LocalKey {
+    i: number;
+    t: number;
+    n: number;
+    [field: string]: any;
+}"#;
+
 #[wasm_bindgen(typescript_custom_section)]
 const KEYGEN_RESPONSE: &'static str = r#"
 interface KeygenResponse {
-    localKey: any;
+    localKey: LocalKey;
+    publicKey: string;
+    publicKeyCompressed: string;
+    timings?: PhaseTiming[];
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const SIGN_BITCOIN_RESPONSE: &'static str = r#"
+interface SignBitcoinResponse {
+    signature: string;
+    sighash: string;
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const SIGN_MULTIPLE_RESULT: &'static str = r#"
+interface SignMultipleResult {
+    signature?: SignResponse;
+    error?: Error;
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const MANAGER_CALL: &'static str = r#"
+interface ManagerCall {
+    method: string;
+    params?: any;
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const VERSION_INFO: &'static str = r#"
+interface VersionInfo {
+    crate: string;
+    protocol: string;
+    commit?: string;
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const LOG_LEVEL: &'static str =
+    r#"type LogLevel = "off" | "error" | "warn" | "info" | "debug" | "trace";"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const LOG_RECORD: &'static str = r#"
+interface LogRecord {
+    level: LogLevel;
+    target: string;
+    message: string;
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const LOCAL_KEY_INFO: &'static str = r#"
+interface LocalKeyInfo {
+    partyIndex: number;
+    parties: number;
+    threshold: number;
     publicKey: string;
 }"#;
 
+#[wasm_bindgen(typescript_custom_section)]
+const HEALTH_RESPONSE: &'static str = r#"
+interface HealthResponse {
+    ok: boolean;
+    latencyMs: number;
+}"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const HASH_ALGORITHM: &'static str =
+    r#"type HashAlgorithm = "none" | "sha256" | "keccak256" | "double-sha256";"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const STATS: &'static str = r#"
+interface Stats {
+    pendingRequests: number;
+    pendingProtocolMessages: number;
+    pendingOfflineMessages: number;
+    pendingPartialMessages: number;
+}"#;
+
 #[wasm_bindgen(typescript_custom_section)]
 const SIGN_RESPONSE: &'static str = r#"
 interface SignResponse {
     r: string;
     s: string;
     recid: number;
+    timings?: PhaseTiming[];
 }
 "#;
 
 #[wasm_bindgen]
 extern "C" {
+    #[wasm_bindgen(typescript_type = "ConnectionState")]
+    pub type ConnectionState;
+    #[wasm_bindgen(typescript_type = "SignBitcoinResponse")]
+    pub type SignBitcoinResponse;
+    #[wasm_bindgen(typescript_type = "SignMultipleResult[]")]
+    pub type SignMultipleResults;
+    #[wasm_bindgen(typescript_type = "ManagerCall[]")]
+    pub type ManagerCalls;
+    #[wasm_bindgen(typescript_type = "VersionInfo")]
+    pub type VersionInfo;
+    #[wasm_bindgen(typescript_type = "LogLevel")]
+    pub type LogLevel;
+    #[wasm_bindgen(typescript_type = "MultiPartyEcdsaOptions")]
+    pub type MultiPartyEcdsaOptions;
+    #[wasm_bindgen(typescript_type = "WebSocketLike")]
+    pub type WebSocketLike;
     #[wasm_bindgen(typescript_type = "SessionKind")]
     pub type SessionKind;
+    #[wasm_bindgen(typescript_type = "HealthResponse")]
+    pub type HealthResponse;
+    #[wasm_bindgen(typescript_type = "Stats")]
+    pub type Stats;
+    #[wasm_bindgen(typescript_type = "HashAlgorithm")]
+    pub type HashAlgorithm;
     #[wasm_bindgen(typescript_type = "GroupCreateResponse")]
     pub type GroupCreateResponse;
     #[wasm_bindgen(typescript_type = "GroupJoinResponse")]
@@ -82,8 +310,20 @@ extern "C" {
     pub type SessionSignupResponse;
     #[wasm_bindgen(typescript_type = "SessionLoginResponse")]
     pub type SessionLoginResponse;
+    #[wasm_bindgen(typescript_type = "SessionReadyNotification")]
+    pub type SessionReadyNotification;
+    #[wasm_bindgen(typescript_type = "AsyncIterableIterator<InspectedMessage>")]
+    pub type InspectedMessages;
+    #[wasm_bindgen(typescript_type = "IteratorResult<InspectedMessage>")]
+    pub type InspectedMessageResult;
     #[wasm_bindgen(typescript_type = "KeygenResponse")]
     pub type KeygenResponse;
+    #[wasm_bindgen(typescript_type = "LocalKey")]
+    pub type LocalKey;
+    #[wasm_bindgen(typescript_type = "KeygenResponse[]")]
+    pub type KeygenResponses;
+    #[wasm_bindgen(typescript_type = "LocalKeyInfo")]
+    pub type LocalKeyInfo;
     #[wasm_bindgen(typescript_type = "SignResponse")]
     pub type SignResponse;
 }
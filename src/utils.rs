@@ -1,2 +1,11 @@
+pub mod compression;
+pub mod crypto;
+pub mod derivation;
+pub mod encryption;
+pub mod idb;
+pub mod logger;
+pub mod secret;
 pub mod serializer;
+pub mod signature;
+pub mod signers;
 pub mod timeout;
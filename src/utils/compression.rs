@@ -0,0 +1,104 @@
+use js_sys::{Reflect, Uint8Array};
+use thiserror::Error;
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    ReadableStream, ReadableStreamDefaultReader, ReadableWritablePair, Response, WritableStream,
+};
+
+// Bound here as `web_sys` only exposes them as unstable APIs
+#[wasm_bindgen]
+extern "C" {
+    type CompressionStream;
+    #[wasm_bindgen(constructor, catch)]
+    fn new(format: &str) -> Result<CompressionStream, JsValue>;
+    #[wasm_bindgen(method, getter)]
+    fn readable(this: &CompressionStream) -> ReadableStream;
+    #[wasm_bindgen(method, getter)]
+    fn writable(this: &CompressionStream) -> WritableStream;
+
+    type DecompressionStream;
+    #[wasm_bindgen(constructor, catch)]
+    fn new(format: &str) -> Result<DecompressionStream, JsValue>;
+    #[wasm_bindgen(method, getter)]
+    fn readable(this: &DecompressionStream) -> ReadableStream;
+    #[wasm_bindgen(method, getter)]
+    fn writable(this: &DecompressionStream) -> WritableStream;
+}
+
+#[derive(Debug, Error)]
+pub enum CompressionError {
+    #[error("compression stream failed due to `{0}`")]
+    StreamFailed(String),
+    #[error("decompressed data is larger than `{0}` bytes")]
+    TooLarge(usize),
+}
+
+impl From<JsValue> for CompressionError {
+    fn from(e: JsValue) -> Self {
+        CompressionError::StreamFailed(e.as_string().unwrap_or("unknown error".into()))
+    }
+}
+
+/// Compresses data with deflate, using the `CompressionStream` API of the
+/// environment (browsers and Node.js 18+).
+pub async fn compress(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    let stream = CompressionStream::new("deflate")?;
+    let output = transform(data, &stream.readable(), &stream.writable())?;
+    let buffer =
+        JsFuture::from(Response::new_with_opt_readable_stream(Some(&output))?.array_buffer()?)
+            .await?;
+    Ok(Uint8Array::new(&buffer).to_vec())
+}
+
+/// Decompresses data compressed with `compress`, reading it chunk by chunk
+/// and failing as soon as it exceeds `max_size` bytes, so a few bytes of
+/// deflated data can't expand into gigabytes.
+pub async fn decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, CompressionError> {
+    let stream = DecompressionStream::new("deflate")?;
+    let output = transform(data, &stream.readable(), &stream.writable())?;
+    let reader = ReadableStreamDefaultReader::new(&output)?;
+    let mut decompressed = Vec::new();
+    loop {
+        let result = JsFuture::from(reader.read()).await?;
+        if Reflect::get(&result, &"done".into())?.is_truthy() {
+            return Ok(decompressed);
+        }
+        let chunk: Uint8Array = Reflect::get(&result, &"value".into())?.unchecked_into();
+        if decompressed.len() + chunk.length() as usize > max_size {
+            let _ = reader.cancel(); // Stops decompressing the rest
+            return Err(CompressionError::TooLarge(max_size));
+        }
+        decompressed.extend(chunk.to_vec());
+    }
+}
+
+/// Pipes data through a transform stream, returning its output.
+fn transform(
+    data: &[u8],
+    readable: &ReadableStream,
+    writable: &WritableStream,
+) -> Result<ReadableStream, CompressionError> {
+    let mut data = data.to_vec();
+    let source = Response::new_with_opt_u8_array(Some(&mut data))?
+        .body()
+        .ok_or_else(|| CompressionError::StreamFailed("missing body".into()))?;
+    Ok(source.pipe_through(&ReadableWritablePair::new(readable, writable)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    async fn rejects_data_decompressing_past_the_limit() {
+        let data = vec![0; 1024 * 1024];
+        let compressed = compress(&data).await.unwrap();
+        assert!(compressed.len() < 16 * 1024);
+
+        let res = decompress(&compressed, data.len() - 1).await;
+        assert!(matches!(res, Err(CompressionError::TooLarge(_))));
+        assert_eq!(decompress(&compressed, data.len()).await.unwrap(), data);
+    }
+}
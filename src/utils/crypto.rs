@@ -0,0 +1,83 @@
+use curv::{
+    arithmetic::Converter,
+    elliptic::curves::{Point, Scalar, Secp256k1},
+    BigInt,
+};
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CryptoError {
+    #[error("invalid point `{0}`, must be a hex encoded secp256k1 point")]
+    InvalidPoint(String),
+    #[error("invalid scalar `{0}`, must be hex encoded, non-zero and lower than the curve order")]
+    InvalidScalar(String),
+    #[error("result is the point at infinity")]
+    PointAtInfinity,
+}
+
+/// Parses a hex encoded point, compressed or not, with or without a `0x`
+/// prefix. Points not on the curve and the point at infinity are rejected.
+pub fn parse_point(encoded: &str) -> Result<Point<Secp256k1>, CryptoError> {
+    let invalid = || CryptoError::InvalidPoint(encoded.into());
+    let bytes =
+        hex::decode(encoded.strip_prefix("0x").unwrap_or(encoded)).map_err(|_| invalid())?;
+    let point = Point::<Secp256k1>::from_bytes(&bytes).map_err(|_| invalid())?;
+    if point.is_zero() {
+        return Err(invalid());
+    }
+    Ok(point)
+}
+
+/// Parses a hex encoded scalar, with or without a `0x` prefix, rejecting
+/// zero and values not lower than the curve order.
+pub fn parse_scalar(encoded: &str) -> Result<Scalar<Secp256k1>, CryptoError> {
+    let n = BigInt::from_hex(encoded.strip_prefix("0x").unwrap_or(encoded))
+        .map_err(|_| CryptoError::InvalidScalar(encoded.into()))?;
+    if n.is_zero() || &n >= Scalar::<Secp256k1>::group_order() {
+        return Err(CryptoError::InvalidScalar(encoded.into()));
+    }
+    Ok(Scalar::from_bigint(&n))
+}
+
+/// Adds two points, failing if they cancel each other out.
+pub fn point_add(
+    a: &Point<Secp256k1>,
+    b: &Point<Secp256k1>,
+) -> Result<Point<Secp256k1>, CryptoError> {
+    let sum = a + b;
+    if sum.is_zero() {
+        return Err(CryptoError::PointAtInfinity);
+    }
+    Ok(sum)
+}
+
+/// Multiplies the generator by a scalar, giving the public key of that
+/// secret.
+pub fn scalar_mul_base(k: &Scalar<Secp256k1>) -> Point<Secp256k1> {
+    Point::generator() * k
+}
+
+/// Returns the Ethereum address of a public key, the last 20 bytes of the
+/// Keccak-256 of its uncompressed coordinates, with the EIP-55 checksum.
+pub fn to_ethereum_address(point: &Point<Secp256k1>) -> String {
+    let encoded = point.to_bytes(false);
+    let hash = Keccak256::digest(&encoded[1..]);
+    let address = hex::encode(&hash[12..]);
+
+    // Uppercase every letter whose nibble in the hash of the address is 8+
+    let checksum = Keccak256::digest(address.as_bytes());
+    let address: String = address
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (checksum[i / 2] >> (4 * (1 - i % 2))) & 0x0f;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("0x{}", address)
+}
@@ -0,0 +1,25 @@
+use curv::elliptic::curves::{Point, Scalar, Secp256k1};
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::keygen::LocalKey;
+
+/// Turns a local key into its child key by adding `tweak` to the shared
+/// secret, so its public key is `public_key + tweak * G` (non-hardened
+/// derivation). The key is changed in place, so no copy of its secrets is
+/// left behind.
+///
+/// Every party must apply the same tweak to its own key, as the secret shares
+/// are all shifted by it and so are the commitments to them.
+pub fn derive_child(local_key: &mut LocalKey<Secp256k1>, tweak: &Scalar<Secp256k1>) {
+    let tweak_point = Point::generator() * tweak;
+
+    local_key.keys_linear.x_i = &local_key.keys_linear.x_i + tweak;
+    local_key.keys_linear.y = &local_key.keys_linear.y + &tweak_point;
+    local_key.y_sum_s = &local_key.y_sum_s + &tweak_point;
+    local_key.pk_vec = local_key
+        .pk_vec
+        .iter()
+        .map(|pk| pk + &tweak_point)
+        .collect();
+    if let Some(commitment) = local_key.vss_scheme.commitments.first_mut() {
+        *commitment = &*commitment + &tweak_point;
+    }
+}
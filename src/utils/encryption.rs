@@ -0,0 +1,108 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::Sha256;
+use thiserror::Error;
+use zeroize::Zeroize;
+
+/// Version of the envelope format, stored as its first byte.
+pub const ENVELOPE_VERSION: u8 = 1;
+/// PBKDF2 iterations used to derive the encryption key from the password.
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("unsupported envelope version `{0}`")]
+    UnsupportedVersion(u8),
+    #[error("invalid envelope")]
+    InvalidEnvelope,
+    #[error("wrong password or corrupted data")]
+    WrongPassword,
+    #[error("wrong key or corrupted data")]
+    WrongKey,
+    #[error("failed to generate random bytes")]
+    Randomness,
+    #[error("failed to encrypt data")]
+    Encrypting,
+}
+
+/// Encrypts data with a key derived from the password using PBKDF2-SHA256,
+/// and returns a base64 envelope with the format
+/// `version || salt || nonce || ciphertext`.
+pub fn encrypt(plaintext: &[u8], password: &str) -> Result<String, EncryptionError> {
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    getrandom::getrandom(&mut salt).map_err(|_| EncryptionError::Randomness)?;
+    getrandom::getrandom(&mut nonce).map_err(|_| EncryptionError::Randomness)?;
+
+    let cipher = cipher(password, &salt);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| EncryptionError::Encrypting)?;
+
+    let mut envelope = Vec::with_capacity(1 + SALT_LENGTH + NONCE_LENGTH + ciphertext.len());
+    envelope.push(ENVELOPE_VERSION);
+    envelope.extend_from_slice(&salt);
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(&ciphertext);
+    Ok(STANDARD.encode(envelope))
+}
+
+/// Decrypts an envelope created by [`encrypt`].
+pub fn decrypt(envelope: &str, password: &str) -> Result<Vec<u8>, EncryptionError> {
+    let envelope = STANDARD
+        .decode(envelope)
+        .map_err(|_| EncryptionError::InvalidEnvelope)?;
+    let (&version, rest) = envelope
+        .split_first()
+        .ok_or(EncryptionError::InvalidEnvelope)?;
+    if version != ENVELOPE_VERSION {
+        return Err(EncryptionError::UnsupportedVersion(version));
+    }
+    if rest.len() < SALT_LENGTH + NONCE_LENGTH {
+        return Err(EncryptionError::InvalidEnvelope);
+    }
+    let (salt, rest) = rest.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+
+    cipher(password, salt)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| EncryptionError::WrongPassword)
+}
+
+/// Encrypts data with a 256-bit key, returning `nonce || ciphertext`.
+pub fn seal(plaintext: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, EncryptionError> {
+    let mut nonce = [0u8; NONCE_LENGTH];
+    getrandom::getrandom(&mut nonce).map_err(|_| EncryptionError::Randomness)?;
+    let ciphertext = Aes256Gcm::new(key.into())
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| EncryptionError::Encrypting)?;
+
+    let mut sealed = Vec::with_capacity(NONCE_LENGTH + ciphertext.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypts data encrypted by [`seal`] with the same key.
+pub fn open(sealed: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, EncryptionError> {
+    if sealed.len() < NONCE_LENGTH {
+        return Err(EncryptionError::InvalidEnvelope);
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+    Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| EncryptionError::WrongKey)
+}
+
+fn cipher(password: &str, salt: &[u8]) -> Aes256Gcm {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, PBKDF2_ITERATIONS, &mut key);
+    let cipher = Aes256Gcm::new(&key.into());
+    key.zeroize();
+    cipher
+}
@@ -0,0 +1,132 @@
+use anyhow::Result;
+use futures::channel::oneshot;
+use js_sys::Reflect;
+use std::{cell::RefCell, rc::Rc};
+use thiserror::Error;
+use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
+use web_sys::{Event, IdbDatabase, IdbFactory, IdbObjectStore, IdbRequest, IdbTransactionMode};
+
+#[derive(Debug, Error)]
+pub enum IdbError {
+    #[error("IndexedDB is not available")]
+    Unavailable,
+    #[error("IndexedDB request failed due to `{0}`")]
+    RequestFailed(String),
+}
+
+impl From<JsValue> for IdbError {
+    fn from(e: JsValue) -> Self {
+        IdbError::RequestFailed(e.as_string().unwrap_or("unknown error".into()))
+    }
+}
+
+/// IndexedDB object store with out-of-line keys, with its requests exposed
+/// as futures.
+pub struct IdbStore {
+    db: IdbDatabase,
+    store_name: String,
+}
+
+impl IdbStore {
+    /// Opens a database, creating the object store if it doesn't exist.
+    pub async fn open(db_name: &str, store_name: &str) -> Result<Self> {
+        let factory = Reflect::get(&js_sys::global(), &"indexedDB".into())
+            .ok()
+            .filter(|factory| !factory.is_undefined())
+            .ok_or(IdbError::Unavailable)?
+            .unchecked_into::<IdbFactory>();
+        let request = factory.open_with_u32(db_name, 1).map_err(IdbError::from)?;
+
+        let request_c = request.clone();
+        let store_name_c = store_name.to_string();
+        let onupgradeneeded = Closure::<dyn FnMut(_)>::new(move |_: Event| {
+            let Ok(db) = request_c.result() else { return };
+            let db: IdbDatabase = db.unchecked_into();
+            if let Err(e) = db.create_object_store(&store_name_c) {
+                log::error!("Failed to create object store: {:?}", e);
+            }
+        });
+        request.set_onupgradeneeded(Some(onupgradeneeded.as_ref().unchecked_ref()));
+        let db = wait_for(&request).await;
+        request.set_onupgradeneeded(None);
+
+        Ok(Self {
+            db: db?.unchecked_into(),
+            store_name: store_name.into(),
+        })
+    }
+
+    /// Stores a value under the given key, replacing the previous one.
+    pub async fn put(&self, key: &str, value: &JsValue) -> Result<()> {
+        let store = self.store(IdbTransactionMode::Readwrite)?;
+        let request = store
+            .put_with_key(value, &key.into())
+            .map_err(IdbError::from)?;
+        wait_for(&request).await?;
+        Ok(())
+    }
+
+    /// Deletes the value stored under the given key, if any.
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        let store = self.store(IdbTransactionMode::Readwrite)?;
+        let request = store.delete(&key.into()).map_err(IdbError::from)?;
+        wait_for(&request).await?;
+        Ok(())
+    }
+
+    /// Returns every stored value.
+    pub async fn get_all(&self) -> Result<Vec<JsValue>> {
+        let store = self.store(IdbTransactionMode::Readonly)?;
+        let request = store.get_all().map_err(IdbError::from)?;
+        let values: js_sys::Array = wait_for(&request).await?.unchecked_into();
+        Ok(values.iter().collect())
+    }
+
+    fn store(&self, mode: IdbTransactionMode) -> Result<IdbObjectStore, IdbError> {
+        let transaction = self
+            .db
+            .transaction_with_str_and_mode(&self.store_name, mode)?;
+        Ok(transaction.object_store(&self.store_name)?)
+    }
+}
+
+impl Drop for IdbStore {
+    fn drop(&mut self) {
+        self.db.close();
+    }
+}
+
+/// Waits for a request to complete, returning its result.
+async fn wait_for(request: &IdbRequest) -> Result<JsValue, IdbError> {
+    let (tx, rx) = oneshot::channel::<Result<JsValue, IdbError>>();
+    let tx = Rc::new(RefCell::new(Some(tx)));
+
+    let tx_c = tx.clone();
+    let request_c = request.clone();
+    let onsuccess = Closure::<dyn FnMut(_)>::new(move |_: Event| {
+        if let Some(tx) = tx_c.borrow_mut().take() {
+            let _ = tx.send(request_c.result().map_err(IdbError::from)); // Ignores dropped request
+        }
+    });
+    let request_c = request.clone();
+    let onerror = Closure::<dyn FnMut(_)>::new(move |_: Event| {
+        if let Some(tx) = tx.borrow_mut().take() {
+            let message = request_c
+                .error()
+                .ok()
+                .flatten()
+                .map(|e| e.message())
+                .unwrap_or("unknown error".into());
+            let _ = tx.send(Err(IdbError::RequestFailed(message))); // Ignores dropped request
+        }
+    });
+    request.set_onsuccess(Some(onsuccess.as_ref().unchecked_ref()));
+    request.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+
+    let result = rx
+        .await
+        .unwrap_or_else(|_| Err(IdbError::RequestFailed("request dropped".into())));
+    request.set_onsuccess(None);
+    request.set_onerror(None);
+    result
+}
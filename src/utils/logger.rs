@@ -0,0 +1,119 @@
+use crate::utils::serializer::serialize_any_to_js;
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use serde::Serialize;
+use std::{cell::RefCell, str::FromStr};
+use thiserror::Error;
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console, js_name = "error")]
+    fn console_error(s: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = "warn")]
+    fn console_warn(s: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = "info")]
+    fn console_info(s: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = "debug")]
+    fn console_debug(s: &str);
+}
+
+thread_local! {
+    // JS functions can't leave the thread they were created in, so records
+    // logged from other threads always go to the console
+    static HANDLER: RefCell<Option<js_sys::Function>> = RefCell::new(None);
+}
+
+static LOGGER: Logger = Logger;
+
+#[derive(Debug, Error)]
+pub enum LoggerError {
+    #[error("invalid log level `{0}`")]
+    InvalidLevel(String),
+}
+
+#[derive(Serialize)]
+struct LogRecord<'a> {
+    level: &'a str,
+    target: &'a str,
+    message: String,
+}
+
+/// Logger writing to the console, or to a JS handler if one is set.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let level = level_name(record.level().to_level_filter());
+        let message = record.args().to_string();
+
+        let handled = HANDLER.with(|handler| {
+            let handler = handler.borrow();
+            let Some(handler) = handler.as_ref() else { return false };
+            let record = LogRecord {
+                level,
+                target: record.target(),
+                message: message.clone(),
+            };
+            if let Ok(record) = serialize_any_to_js(record) {
+                // A failing handler can't be logged without calling it again
+                let _ = handler.call1(&JsValue::NULL, &record);
+            }
+            true
+        });
+        if handled {
+            return;
+        }
+
+        let line = format!("{} {}: {}", record.level(), record.target(), message);
+        match record.level() {
+            Level::Error => console_error(&line),
+            Level::Warn => console_warn(&line),
+            Level::Info => console_info(&line),
+            Level::Debug | Level::Trace => console_debug(&line),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger, logging records up to the given level.
+pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Sends the records logged from the current thread to `handler` instead
+/// of the console, or back to the console if `None`.
+pub fn set_handler(handler: Option<js_sys::Function>) {
+    HANDLER.with(|current| *current.borrow_mut() = handler);
+}
+
+/// Sets the maximum level of the logged records.
+pub fn set_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+/// Parses a level name, case insensitively.
+pub fn parse_level(level: &str) -> Result<LevelFilter, LoggerError> {
+    LevelFilter::from_str(level).map_err(|_| LoggerError::InvalidLevel(level.into()))
+}
+
+/// Returns the lowercase name of a level, as used by the JS API.
+fn level_name(level: LevelFilter) -> &'static str {
+    match level {
+        LevelFilter::Off => "off",
+        LevelFilter::Error => "error",
+        LevelFilter::Warn => "warn",
+        LevelFilter::Info => "info",
+        LevelFilter::Debug => "debug",
+        LevelFilter::Trace => "trace",
+    }
+}
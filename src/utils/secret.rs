@@ -0,0 +1,79 @@
+use curv::elliptic::curves::{Point, Secp256k1};
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::state_machine::keygen::LocalKey;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::ops::{Deref, DerefMut};
+use wasm_bindgen::JsValue;
+use zeroize::Zeroize;
+
+/// Local key that zeroizes its Paillier secret when dropped, which
+/// `LocalKey` doesn't do by itself, so no exit path leaves it behind. The
+/// secret share is a `Scalar`, which already zeroizes itself.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SecretLocalKey(LocalKey<Secp256k1>);
+
+impl SecretLocalKey {
+    pub fn new(local_key: LocalKey<Secp256k1>) -> Self {
+        Self(local_key)
+    }
+}
+
+impl Deref for SecretLocalKey {
+    type Target = LocalKey<Secp256k1>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for SecretLocalKey {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for SecretLocalKey {
+    fn drop(&mut self) {
+        self.0.paillier_dk.p.zeroize();
+        self.0.paillier_dk.q.zeroize();
+    }
+}
+
+/// Deserializes a secret from JS through its JSON text, which is zeroized
+/// afterwards. Deserializing the JS value directly would leave an unwiped
+/// copy of each of its strings in wasm memory.
+pub fn deserialize_secret_from_js<T: DeserializeOwned>(value: &JsValue) -> Option<T> {
+    let mut json = js_sys::JSON::stringify(value).ok()?.as_string()?;
+    let secret = serde_json::from_str(&json).ok();
+    json.zeroize();
+    secret
+}
+
+/// Checks the secret share of a local key matches its public commitments and
+/// the group public key, which catches corrupted keys and keys mixed from
+/// different groups before they're used.
+pub fn is_consistent(local_key: &LocalKey<Secp256k1>) -> bool {
+    let Some(own_public_share) = usize::from(local_key.i)
+        .checked_sub(1)
+        .and_then(|index| local_key.pk_vec.get(index))
+    else {
+        return false;
+    };
+    local_key.pk_vec.len() == usize::from(local_key.n)
+        && &(Point::generator() * &local_key.keys_linear.x_i) == own_public_share
+        && local_key.keys_linear.y == local_key.y_sum_s
+        && local_key.vss_scheme.commitments.first() == Some(&local_key.y_sum_s)
+}
+
+/// Checks the public share of every party is the evaluation of the VSS
+/// commitments of the group at its index, and that the commitments have the
+/// degree of the threshold. Along with [`is_consistent`], it ensures any
+/// `t + 1` public shares interpolate to the group public key.
+pub fn matches_commitments(local_key: &LocalKey<Secp256k1>) -> bool {
+    local_key.vss_scheme.commitments.len() == usize::from(local_key.t) + 1
+        && (1..=local_key.n)
+            .zip(&local_key.pk_vec)
+            .all(|(index, public_share)| {
+                &local_key.vss_scheme.get_point_commitment(index) == public_share
+            })
+}
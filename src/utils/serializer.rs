@@ -1,3 +1,4 @@
+use js_sys::Reflect;
 use serde::Serialize;
 use wasm_bindgen::{JsError, JsValue};
 
@@ -13,18 +14,60 @@ pub fn serialize_str_error_to_js<T: ToString>(error: T) -> JsError {
     JsError::new(&error.to_string())
 }
 
+/// Returns the message of an error followed by every cause of its chain.
+pub fn format_error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message = format!("{}: {}", message, cause);
+        source = cause.source();
+    }
+    message
+}
+
 pub fn serialize_serializable_error_to_js<T: serde::Serialize>(error: T) -> JsError {
     JsError::new(&serde_json::to_string(&error).unwrap_or("Unknown error".into()))
 }
 
 pub fn serialize_response_to_js(
     res: json_rpc_types::Response<serde_json::Value, serde_json::Value>,
-) -> Result<JsValue, JsError> {
+) -> Result<JsValue, JsValue> {
     let serializer = get_json_serializer();
     match res.payload {
-        Ok(message) => Ok(message.serialize(&serializer)?),
-        Err(err) => Err(JsError::new(&serde_json::to_string_pretty(&err)?)), //FIXME
+        Ok(message) => Ok(message
+            .serialize(&serializer)
+            .map_err(serialize_str_error_to_js)?),
+        Err(err) => Err(serialize_rpc_error_to_js(err)),
+    }
+}
+
+/// Creates a JS `Error` keeping the `code` and `data` of the JSON-RPC error
+/// as properties, so callers can branch on them.
+pub fn serialize_rpc_error_to_js(error: json_rpc_types::Error<serde_json::Value>) -> JsValue {
+    let message = error.message.as_str().to_owned();
+    serialize_named_rpc_error_to_js(error, "RpcError", &message)
+}
+
+/// Creates a JS `Error` like `serialize_rpc_error_to_js`, with the given
+/// name and message.
+pub fn serialize_named_rpc_error_to_js(
+    error: json_rpc_types::Error<serde_json::Value>,
+    name: &str,
+    message: &str,
+) -> JsValue {
+    let js_error = js_sys::Error::new(message);
+    js_error.set_name(name);
+    let _ = Reflect::set(
+        &js_error,
+        &"code".into(),
+        &JsValue::from_f64(error.code.code() as f64),
+    );
+    if let Some(data) = error.data {
+        if let Ok(data) = data.serialize(&get_json_serializer()) {
+            let _ = Reflect::set(&js_error, &"data".into(), &data);
+        }
     }
+    js_error.into()
 }
 
 pub fn serialize_any_to_js<T: serde::Serialize>(message: T) -> Result<JsValue, JsError> {
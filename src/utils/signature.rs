@@ -0,0 +1,300 @@
+use curv::{
+    arithmetic::Converter,
+    elliptic::curves::{Point, Scalar, Secp256k1},
+    BigInt,
+};
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2020::party_i::SignatureRecid;
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SignatureError {
+    #[error("invalid recovery id `{0}`, must be between 0 and 3")]
+    InvalidRecoveryId(u8),
+    #[error("invalid signature")]
+    InvalidSignature,
+    #[error("invalid hash algorithm `{0}`, must be none, sha256, keccak256 or double-sha256")]
+    InvalidHashAlgorithm(String),
+    #[error("message hash is not lower than the curve order")]
+    HashOutOfRange,
+    #[error("recovery id `{0}` can't be encoded in an Ethereum signature")]
+    UnsupportedRecoveryId(u8),
+    #[error("invalid chain id `{0}`, must be a positive integer whose `v` is a safe integer")]
+    InvalidChainId(String),
+}
+
+/// Largest integer a JS number holds exactly.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Hash function reducing a message to the value that's signed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// The message is signed as is, so it's expected to be a digest already.
+    #[default]
+    None,
+    Sha256,
+    Keccak256,
+    /// SHA-256 applied twice, as Bitcoin does.
+    DoubleSha256,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(HashAlgorithm::None),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "keccak256" => Ok(HashAlgorithm::Keccak256),
+            "double-sha256" => Ok(HashAlgorithm::DoubleSha256),
+            _ => Err(SignatureError::InvalidHashAlgorithm(s.into())),
+        }
+    }
+}
+
+impl HashAlgorithm {
+    /// Hashes a message, checking the digest is lower than the curve order
+    /// so it's signed as is rather than reduced. Unhashed messages are
+    /// returned unchanged.
+    pub fn hash(self, message: &[u8]) -> Result<Vec<u8>, SignatureError> {
+        let digest = match self {
+            HashAlgorithm::None => return Ok(message.to_vec()),
+            HashAlgorithm::Sha256 => Sha256::digest(message).to_vec(),
+            HashAlgorithm::Keccak256 => Keccak256::digest(message).to_vec(),
+            HashAlgorithm::DoubleSha256 => Sha256::digest(Sha256::digest(message)).to_vec(),
+        };
+        if &BigInt::from_bytes(&digest) >= Scalar::<Secp256k1>::group_order() {
+            return Err(SignatureError::HashOutOfRange);
+        }
+        Ok(digest)
+    }
+}
+
+/// Normalizes a signature to its low-S form, replacing `s` with `n - s` when
+/// `s > n/2` and flipping the recovery id parity accordingly, as required by
+/// verifiers that reject malleable signatures (e.g. Ethereum and Bitcoin).
+pub fn normalize_s(signature: SignatureRecid) -> SignatureRecid {
+    let half_order = Scalar::<Secp256k1>::group_order() >> 1;
+    if signature.s.to_bigint() <= half_order {
+        return signature;
+    }
+    SignatureRecid {
+        s: -signature.s,
+        recid: signature.recid ^ 1,
+        ..signature
+    }
+}
+
+/// Recovers the public key that produced a signature of the given message,
+/// as `ecrecover` does.
+pub fn recover_public_key(
+    message: &BigInt,
+    r: &BigInt,
+    s: &BigInt,
+    recid: u8,
+) -> Result<Point<Secp256k1>, SignatureError> {
+    if recid > 3 {
+        return Err(SignatureError::InvalidRecoveryId(recid));
+    }
+    let order = Scalar::<Secp256k1>::group_order();
+    if r.is_zero() || s.is_zero() || r >= order || s >= order {
+        return Err(SignatureError::InvalidSignature);
+    }
+
+    // Rebuild the point R from its x coordinate and the parity of y, both
+    // encoded in the recovery id
+    let x = if recid & 2 != 0 { r + order } else { r.clone() };
+    let x = x.to_bytes();
+    if x.len() > 32 {
+        return Err(SignatureError::InvalidSignature);
+    }
+    let mut encoded = vec![0x02 | (recid & 1)];
+    encoded.resize(33 - x.len(), 0);
+    encoded.extend(x);
+    let big_r =
+        Point::<Secp256k1>::from_bytes(&encoded).map_err(|_| SignatureError::InvalidSignature)?;
+
+    // Q = r^-1 * (s * R - e * G)
+    let r_inv = Scalar::<Secp256k1>::from_bigint(r)
+        .invert()
+        .ok_or(SignatureError::InvalidSignature)?;
+    let s = Scalar::<Secp256k1>::from_bigint(s);
+    let e = Scalar::<Secp256k1>::from_bigint(message);
+    let public_key = &(&(&big_r * &s) - &(Point::generator() * &e)) * &r_inv;
+    if public_key.is_zero() {
+        return Err(SignatureError::InvalidSignature);
+    }
+    Ok(public_key)
+}
+
+/// Encodes a scalar as 32 big-endian bytes, left padded with zeros.
+pub fn to_fixed_bytes(n: &BigInt) -> [u8; 32] {
+    let bytes = n.to_bytes();
+    let mut fixed = [0u8; 32];
+    fixed[32 - bytes.len()..].copy_from_slice(&bytes);
+    fixed
+}
+
+/// Encodes a signature in DER, as a `SEQUENCE` of the `r` and `s` integers.
+pub fn to_der(r: &BigInt, s: &BigInt) -> Vec<u8> {
+    let mut body = Vec::with_capacity(70);
+    for n in [r, s] {
+        // Integers are signed, so a leading high bit needs a zero byte
+        let mut bytes = n.to_bytes();
+        if bytes.is_empty() || bytes[0] & 0x80 != 0 {
+            bytes.insert(0, 0);
+        }
+        body.push(0x02);
+        body.push(bytes.len() as u8);
+        body.extend(bytes);
+    }
+    let mut der = vec![0x30, body.len() as u8];
+    der.extend(body);
+    der
+}
+
+/// Returns the `v` value of an Ethereum signature without replay protection,
+/// `recid + 27`, as used by legacy transactions and signed messages.
+pub fn legacy_v(recid: u8) -> Result<u64, SignatureError> {
+    if recid > 1 {
+        return Err(SignatureError::UnsupportedRecoveryId(recid));
+    }
+    Ok(u64::from(recid) + 27)
+}
+
+/// Returns the `v` value of an EIP-155 transaction signature,
+/// `recid + chainId * 2 + 35`, checking it's a safe integer so it can be
+/// handed to JS as a number.
+pub fn eip155_v(recid: u8, chain_id: u64) -> Result<u64, SignatureError> {
+    if recid > 1 {
+        return Err(SignatureError::UnsupportedRecoveryId(recid));
+    }
+    chain_id
+        .checked_mul(2)
+        .and_then(|v| v.checked_add(35 + u64::from(recid)))
+        .filter(|v| chain_id > 0 && *v <= MAX_SAFE_INTEGER)
+        .ok_or_else(|| SignatureError::InvalidChainId(chain_id.to_string()))
+}
+
+/// Computes the digest signed for EIP-712 typed data,
+/// `keccak256(0x1901 || domainSeparator || structHash)`.
+pub fn eip712_digest(domain_separator: &[u8; 32], struct_hash: &[u8; 32]) -> [u8; 32] {
+    Keccak256::new()
+        .chain_update([0x19, 0x01])
+        .chain_update(domain_separator)
+        .chain_update(struct_hash)
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(s: &BigInt, recid: u8) -> SignatureRecid {
+        SignatureRecid {
+            r: Scalar::from_bigint(&BigInt::from(1u32)),
+            s: Scalar::from_bigint(s),
+            recid,
+        }
+    }
+
+    #[test]
+    fn keeps_low_s_signatures() {
+        let half_order = Scalar::<Secp256k1>::group_order() >> 1;
+        for s in [BigInt::from(1u32), half_order] {
+            let normalized = normalize_s(signature(&s, 1));
+            assert_eq!(normalized.s.to_bigint(), s);
+            assert_eq!(normalized.recid, 1);
+        }
+    }
+
+    #[test]
+    fn negates_high_s_and_flips_the_recovery_id() {
+        let order = Scalar::<Secp256k1>::group_order();
+        let half_order = order >> 1;
+        let (one, two) = (BigInt::from(1u32), BigInt::from(2u32));
+        let cases = [
+            (order - &one, 0, one.clone(), 1),
+            (&half_order + &one, 1, half_order, 0),
+            (order - &two, 3, two, 2),
+        ];
+        for (s, recid, expected_s, expected_recid) in cases {
+            let normalized = normalize_s(signature(&s, recid));
+            assert_eq!(normalized.s.to_bigint(), expected_s);
+            assert_eq!(normalized.recid, expected_recid);
+        }
+    }
+
+    #[test]
+    fn pads_scalars_to_32_bytes() {
+        let n = BigInt::from_hex(&"ab".repeat(31)).unwrap();
+        let mut expected = [0xab; 32];
+        expected[0] = 0;
+        assert_eq!(to_fixed_bytes(&n), expected);
+
+        let mut expected = [0; 32];
+        expected[30..].copy_from_slice(&[0x01, 0xff]);
+        assert_eq!(to_fixed_bytes(&BigInt::from_hex("1ff").unwrap()), expected);
+    }
+
+    #[test]
+    fn encodes_minimal_der_integers() {
+        let r = BigInt::from_hex("7f").unwrap();
+        let s = BigInt::from_hex("80").unwrap();
+        assert_eq!(
+            to_der(&r, &s),
+            [0x30, 0x07, 0x02, 0x01, 0x7f, 0x02, 0x02, 0x00, 0x80]
+        );
+
+        // A value with leading zero bytes is encoded without them, unless
+        // its high bit is set
+        let r = BigInt::from_hex(&format!("00{}", "ff".repeat(31))).unwrap();
+        let s = BigInt::from_hex(&format!("0000{}", "01".repeat(30))).unwrap();
+        let mut expected = vec![0x30, 0x42, 0x02, 0x20, 0x00];
+        expected.extend([0xff; 31]);
+        expected.extend([0x02, 0x1e]);
+        expected.extend([0x01; 30]);
+        assert_eq!(to_der(&r, &s), expected);
+    }
+
+    #[test]
+    fn computes_legacy_v() {
+        assert_eq!(legacy_v(0).unwrap(), 27);
+        assert_eq!(legacy_v(1).unwrap(), 28);
+        assert!(matches!(
+            legacy_v(2),
+            Err(SignatureError::UnsupportedRecoveryId(2))
+        ));
+    }
+
+    #[test]
+    fn computes_eip155_v() {
+        // Mainnet, Optimism, Polygon, Base and Arbitrum One
+        let cases = [(1, 37), (10, 55), (137, 309), (8453, 16941), (42161, 84357)];
+        for (chain_id, v) in cases {
+            assert_eq!(eip155_v(0, chain_id).unwrap(), v);
+            assert_eq!(eip155_v(1, chain_id).unwrap(), v + 1);
+        }
+        assert!(matches!(
+            eip155_v(2, 1),
+            Err(SignatureError::UnsupportedRecoveryId(2))
+        ));
+    }
+
+    #[test]
+    fn rejects_chain_ids_whose_v_is_not_a_safe_integer() {
+        let largest = (MAX_SAFE_INTEGER - 36) / 2;
+        assert_eq!(eip155_v(1, largest).unwrap(), MAX_SAFE_INTEGER - 1);
+        assert_eq!(eip155_v(0, largest + 1).unwrap(), MAX_SAFE_INTEGER);
+        assert!(eip155_v(1, largest + 1).is_err());
+        for chain_id in [0, largest + 2, u64::MAX / 2, u64::MAX] {
+            assert!(matches!(
+                eip155_v(0, chain_id),
+                Err(SignatureError::InvalidChainId(_))
+            ));
+        }
+    }
+}
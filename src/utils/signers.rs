@@ -0,0 +1,61 @@
+use std::collections::BTreeSet;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SignersError {
+    #[error("party `{0}` is included more than once")]
+    DuplicatedParty(u16),
+    #[error("party `{0}` is out of range, must be between 1 and {1}")]
+    PartyOutOfRange(u16, u16),
+    #[error("not enough signers, got {0} but at least {1} are required")]
+    NotEnoughSigners(usize, usize),
+    #[error("party `{0}` must be included in the signers")]
+    MissingOwnParty(u16),
+}
+
+/// Checks the signers of a key with threshold `t` among `n` parties are
+/// distinct, in range and enough to sign, and include party `i`.
+pub fn validate_signers(parties: &[u16], i: u16, t: u16, n: u16) -> Result<(), SignersError> {
+    let mut seen = BTreeSet::new();
+    for &party in parties {
+        if party == 0 || party > n {
+            return Err(SignersError::PartyOutOfRange(party, n));
+        }
+        if !seen.insert(party) {
+            return Err(SignersError::DuplicatedParty(party));
+        }
+    }
+    let required = usize::from(t) + 1;
+    if parties.len() < required {
+        return Err(SignersError::NotEnoughSigners(parties.len(), required));
+    }
+    if !seen.contains(&i) {
+        return Err(SignersError::MissingOwnParty(i));
+    }
+    Ok(())
+}
+
+/// Sorts the signers and removes duplicates, so every signer passes them to
+/// the protocol in the same order.
+pub fn normalize_signers(parties: &[u16]) -> Vec<u16> {
+    let parties: BTreeSet<u16> = parties.iter().copied().collect();
+    parties.into_iter().collect()
+}
+
+/// Selects the minimal set of signers among the available parties, that is
+/// party `i` plus the `t` available parties with the lowest indices.
+///
+/// Signers given the same available parties agree on the set, as long as
+/// they are among the lowest indices.
+pub fn select_signers(available: &[u16], i: u16, t: u16, n: u16) -> Result<Vec<u16>, SignersError> {
+    let mut available: BTreeSet<u16> = available.iter().copied().collect();
+    available.insert(i);
+    let mut signers: Vec<u16> = available.into_iter().take(usize::from(t) + 1).collect();
+    if !signers.contains(&i) {
+        signers.pop();
+        signers.push(i);
+        signers.sort_unstable();
+    }
+    validate_signers(&signers, i, t, n)?;
+    Ok(signers)
+}